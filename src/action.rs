@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::{Entity, Stairs, Stat, PLAYER},
+    game::{self, Game},
+    message::Category,
+    status::Effect,
+};
+
+// everything the player can do, the front-end only turns key presses into these
+// so anything else (replays, tests, bots) can drive the player the same way
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    // a step in one of the 8 directions, walking into a monster attacks it
+    // and walking into a door opens it
+    Move(i32, i32),
+    // attacks a monster next to the player without ever moving
+    Attack(i32, i32),
+    Wait,
+    PickUp,
+    // the index of the item in `game.inventory`
+    Use(usize),
+    // uses an item on the tile at x, y, for items that need a target
+    UseAt(usize, i32, i32),
+    Drop(usize),
+    Descend,
+    Ascend,
+    LevelUp(Stat),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TurnOutcome {
+    // the monsters had their turn after the player, several for a slow step
+    TookTurn,
+    // done without giving the monsters a turn, like picking up an item
+    FreeAction,
+    // could not be done right now, nothing changed
+    Invalid,
+}
+
+fn is_step(dx: i32, dy: i32) -> bool {
+    dx.abs() <= 1 && dy.abs() <= 1
+}
+
+pub fn apply_action(game: &mut Game, entities: &mut Vec<Entity>, action: Action) -> TurnOutcome {
+    use TurnOutcome::{FreeAction, Invalid, TookTurn};
+
+    // messages from this action and the monsters' answer to it belong to the coming turn
+    game.messages.set_turn(game.turn + 1);
+    let is_alive = entities[PLAYER].is_alive();
+    let previous_position = entities[PLAYER].get_coordinates();
+    let was_blind = entities[PLAYER].has_effect(Effect::Blind);
+    // a stunned player loses the turn and a confused one stumbles in a random direction
+    let action = match action {
+        Action::Move(dx, dy) | Action::Attack(dx, dy)
+            if is_alive && is_step(dx, dy) && entities[PLAYER].has_effect(Effect::Stunned) =>
+        {
+            let color = Effect::Stunned.color();
+            game.messages
+                .add("You are stunned and can not move.", color, Category::Combat);
+            Action::Wait
+        }
+        Action::Move(dx, dy)
+            if is_alive
+                && is_step(dx, dy)
+                && (dx, dy) != (0, 0)
+                && entities[PLAYER].has_effect(Effect::Confused) =>
+        {
+            let (dx, dy) = Entity::random_direction(game);
+            Action::Move(dx, dy)
+        }
+        action => action,
+    };
+    let outcome = match action {
+        Action::Move(dx, dy) if is_alive && is_step(dx, dy) => {
+            Entity::player_move_or_attack(PLAYER, dx, dy, game, entities);
+            TookTurn
+        }
+        Action::Attack(dx, dy) if is_alive && is_step(dx, dy) => {
+            if Entity::player_attack(dx, dy, game, entities) {
+                TookTurn
+            } else {
+                Invalid
+            }
+        }
+        Action::Wait if is_alive => TookTurn,
+        Action::PickUp if is_alive => {
+            let item_id = entities.iter().position(|entity| {
+                entity.get_coordinates() == entities[PLAYER].get_coordinates()
+                    && entity.get_item().is_some()
+            });
+            match item_id {
+                Some(item_id) => {
+                    Entity::pick_item_up(item_id, game, entities);
+                    FreeAction
+                }
+                None => Invalid,
+            }
+        }
+        Action::Use(inventory_id) if is_alive && inventory_id < game.inventory.len() => {
+            game::use_item(inventory_id, None, game, entities);
+            FreeAction
+        }
+        Action::UseAt(inventory_id, x, y) if is_alive && inventory_id < game.inventory.len() => {
+            game::use_item(inventory_id, Some((x, y)), game, entities);
+            FreeAction
+        }
+        Action::Drop(inventory_id) if is_alive && inventory_id < game.inventory.len() => {
+            Entity::drop_item(inventory_id, game, entities);
+            FreeAction
+        }
+        Action::Descend
+            if is_alive && game::stairs_under_player(entities) == Some(Stairs::Down) =>
+        {
+            game::next_level(game, entities);
+            FreeAction
+        }
+        Action::Ascend if is_alive && game::stairs_under_player(entities) == Some(Stairs::Up) => {
+            game::previous_level(game, entities);
+            FreeAction
+        }
+        Action::LevelUp(stat) if entities[PLAYER].can_level_up() => {
+            Entity::level_up(stat, game, entities);
+            FreeAction
+        }
+        _ => Invalid,
+    };
+
+    if entities[PLAYER].get_coordinates() != previous_position {
+        game.compute_fov(entities);
+    }
+    if outcome == TookTurn {
+        // wading through water or climbing over rubble takes the turns its tile costs
+        let (x, y) = entities[PLAYER].get_coordinates();
+        let turns = if (x, y) != previous_position && matches!(action, Action::Move(..)) {
+            game.map[x as usize][y as usize].get_movement_cost()
+        } else {
+            1
+        };
+        for turn in 0..turns {
+            if turn > 0 {
+                if !entities[PLAYER].is_alive() {
+                    break;
+                }
+                game.messages.set_turn(game.turn + 1);
+            }
+            game.turn += 1;
+            Entity::mobs_turn(game, entities, true);
+            Entity::tick_effects(game, entities);
+        }
+    }
+    if entities[PLAYER].has_effect(Effect::Blind) != was_blind {
+        game.compute_fov(entities);
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::WHITE,
+        entity::DeathCallback,
+        game::new_game,
+        tile::{Tile, TileKind},
+    };
+
+    // a step from the player onto a tile nothing stands on
+    fn free_step(game: &Game, entities: &[Entity]) -> (i32, i32) {
+        let (x, y) = entities[PLAYER].get_coordinates();
+        [
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (-1, -1),
+            (1, -1),
+            (-1, 1),
+        ]
+        .into_iter()
+        .find(|&(dx, dy)| !Tile::is_blocked(x + dx, y + dy, &game.map, entities))
+        .expect("the player is not walled in")
+    }
+
+    #[test]
+    fn moving_takes_a_turn() {
+        let (mut game, mut entities) = new_game(1);
+        let (x, y) = entities[PLAYER].get_coordinates();
+        let (dx, dy) = free_step(&game, &entities);
+        let outcome = apply_action(&mut game, &mut entities, Action::Move(dx, dy));
+        assert_eq!(outcome, TurnOutcome::TookTurn);
+        assert_eq!(entities[PLAYER].get_coordinates(), (x + dx, y + dy));
+        assert_eq!(game.turn, 1);
+        let outcome = apply_action(&mut game, &mut entities, Action::Move(2, 0));
+        assert_eq!(outcome, TurnOutcome::Invalid);
+        assert_eq!(game.turn, 1);
+    }
+
+    #[test]
+    fn wading_through_water_takes_longer() {
+        let (mut game, mut entities) = new_game(3);
+        let (x, y) = entities[PLAYER].get_coordinates();
+        let (dx, dy) = free_step(&game, &entities);
+        game.set_tile_kind(x + dx, y + dy, TileKind::Water);
+        let cost = game.map[(x + dx) as usize][(y + dy) as usize].get_movement_cost();
+        assert!(cost > 1);
+
+        let outcome = apply_action(&mut game, &mut entities, Action::Move(dx, dy));
+        assert_eq!(outcome, TurnOutcome::TookTurn);
+        assert_eq!(game.turn, cost as u32);
+        // stepping back out onto the floor is a normal turn again
+        apply_action(&mut game, &mut entities, Action::Move(-dx, -dy));
+        assert_eq!(game.turn, cost as u32 + 1);
+    }
+
+    #[test]
+    fn attacking_hurts_the_monster() {
+        let (mut game, mut entities) = new_game(2);
+        let (x, y) = entities[PLAYER].get_coordinates();
+        let (dx, dy) = free_step(&game, &entities);
+        let mut dummy = Entity::new(x + dx, y + dy, 'd', WHITE, "dummy", true);
+        dummy.make_alive();
+        dummy.make_fighter(10, 10, 0, 0, 5, DeathCallback::Monster);
+        entities.push(dummy);
+        let dummy = entities.len() - 1;
+
+        let power = entities[PLAYER].power(&game);
+        let outcome = apply_action(&mut game, &mut entities, Action::Attack(dx, dy));
+        assert_eq!(outcome, TurnOutcome::TookTurn);
+        let hp = entities[dummy].get_fighter().map(|f| f.get_hp().0);
+        assert_eq!(hp, Some(10 - power));
+
+        // attacking thin air is not a turn
+        let outcome = apply_action(&mut game, &mut entities, Action::Attack(-dx, -dy));
+        assert_eq!(outcome, TurnOutcome::Invalid);
+    }
+}
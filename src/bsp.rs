@@ -0,0 +1,116 @@
+use rand::Rng;
+
+use crate::{
+    entity::Entity,
+    rng::GameRng,
+    room::{Room, ROOM_MAX_SIZE, ROOM_MIN_SIZE},
+    tile::{Map, MAP_HEIGHT, MAP_WIDTH},
+};
+
+// a leaf never gets smaller than the smallest room plus a wall on each side
+const MIN_LEAF_SIZE: i32 = ROOM_MIN_SIZE + 2;
+// leaves bigger than this are always split further
+const MAX_LEAF_SIZE: i32 = 2 * ROOM_MAX_SIZE;
+// chance to stop splitting a leaf that is already small enough
+const STOP_SPLIT_CHANCE: f64 = 0.25;
+
+// part of the map that is either split in two or gets a single room
+#[derive(Clone, Copy)]
+struct Leaf {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+impl Leaf {
+    fn split(&self, rng: &mut GameRng) -> Option<(Leaf, Leaf)> {
+        let can_split_vertically = self.w >= 2 * MIN_LEAF_SIZE;
+        let can_split_horizontally = self.h >= 2 * MIN_LEAF_SIZE;
+        if !can_split_vertically && !can_split_horizontally {
+            return None;
+        }
+        let is_too_big = self.w > MAX_LEAF_SIZE || self.h > MAX_LEAF_SIZE;
+        if !is_too_big && rng.gen_bool(STOP_SPLIT_CHANCE) {
+            return None;
+        }
+
+        // cut across the longer side so leaves stay roughly square
+        let split_vertically = match (can_split_vertically, can_split_horizontally) {
+            (true, false) => true,
+            (false, true) => false,
+            _ if self.w as f32 / self.h as f32 >= 1.25 => true,
+            _ if self.h as f32 / self.w as f32 >= 1.25 => false,
+            _ => rng.gen::<bool>(),
+        };
+
+        if split_vertically {
+            let at = rng.gen_range(MIN_LEAF_SIZE..=self.w - MIN_LEAF_SIZE);
+            Some((
+                Leaf { w: at, ..*self },
+                Leaf {
+                    x: self.x + at,
+                    w: self.w - at,
+                    ..*self
+                },
+            ))
+        } else {
+            let at = rng.gen_range(MIN_LEAF_SIZE..=self.h - MIN_LEAF_SIZE);
+            Some((
+                Leaf { h: at, ..*self },
+                Leaf {
+                    y: self.y + at,
+                    h: self.h - at,
+                    ..*self
+                },
+            ))
+        }
+    }
+
+    fn random_room(&self, rng: &mut GameRng) -> Room {
+        let w = rng.gen_range(ROOM_MIN_SIZE..=ROOM_MAX_SIZE.min(self.w));
+        let h = rng.gen_range(ROOM_MIN_SIZE..=ROOM_MAX_SIZE.min(self.h));
+        let x = rng.gen_range(self.x..=self.x + self.w - w);
+        let y = rng.gen_range(self.y..=self.y + self.h - h);
+        Room::new(x, y, w, h)
+    }
+}
+
+// carves the rooms of the leaf and returns a point inside one of them,
+// so the parent can join both halves with a short tunnel
+fn carve_leaf(leaf: Leaf, map: &mut Map, rooms: &mut Vec<Room>, rng: &mut GameRng) -> (i32, i32) {
+    match leaf.split(rng) {
+        Some((first, second)) => {
+            let first_center = carve_leaf(first, map, rooms, rng);
+            let second_center = carve_leaf(second, map, rooms, rng);
+            Room::connect(first_center, second_center, map, rng);
+            if rng.gen::<bool>() {
+                first_center
+            } else {
+                second_center
+            }
+        }
+        None => {
+            let room = leaf.random_room(rng);
+            room.put_on_map(map);
+            let center = room.get_center();
+            rooms.push(room);
+            center
+        }
+    }
+}
+
+pub fn generate_bsp(map: &mut Map, entities: &mut Vec<Entity>, rng: &mut GameRng) {
+    // the last row and column are left out so the map always has an outer wall
+    let root = Leaf {
+        x: 0,
+        y: 0,
+        w: MAP_WIDTH - 1,
+        h: MAP_HEIGHT - 1,
+    };
+    let mut rooms = Vec::<Room>::new();
+    carve_leaf(root, map, &mut rooms, rng);
+    Room::place_doors(&rooms, map, rng);
+
+    Room::place_entities(&mut rooms, map, entities, rng);
+}
@@ -0,0 +1,227 @@
+use rand::Rng;
+
+use crate::{
+    entity::{Entity, Stairs, FREDERIC, PLAYER},
+    rng::GameRng,
+    tile::{Map, Tile, TileKind, MAP_HEIGHT, MAP_WIDTH},
+};
+
+// chance for a tile to start as a wall before smoothing
+const FILL_PROBABILITY: f64 = 0.45;
+const SMOOTHING_ITERATIONS: i32 = 5;
+// a tile turns into a wall if at least this many tiles of its 3x3 block are walls
+const WALL_THRESHOLD: i32 = 5;
+// caves with a smaller connected area are thrown away and generated again
+const MIN_CAVE_SIZE: usize = 600;
+// no monsters or items are spawned this close to the player start
+const SAFE_RADIUS: i32 = 6;
+const WATER_POOLS: i32 = 3;
+const GRASS_PATCHES: i32 = 5;
+const RUBBLE_PATCHES: i32 = 3;
+// number of tiles in one pool or patch
+const PATCH_SIZE: usize = 30;
+const LAVA_POOLS: i32 = 2;
+const CHASMS: i32 = 2;
+// lava and chasms are smaller, they can not be walked through
+const HAZARD_SIZE: usize = 12;
+
+fn is_border(x: i32, y: i32) -> bool {
+    x == 0 || y == 0 || x == MAP_WIDTH - 1 || y == MAP_HEIGHT - 1
+}
+
+fn random_fill(map: &mut Map, rng: &mut GameRng) {
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            map[x as usize][y as usize] = if is_border(x, y) || rng.gen_bool(FILL_PROBABILITY) {
+                Tile::wall()
+            } else {
+                Tile::empty()
+            };
+        }
+    }
+}
+
+fn count_walls_around(x: i32, y: i32, map: &Map) -> i32 {
+    let mut walls = 0;
+    for nx in x - 1..=x + 1 {
+        for ny in y - 1..=y + 1 {
+            let outside = nx < 0 || ny < 0 || nx >= MAP_WIDTH || ny >= MAP_HEIGHT;
+            if outside || !map[nx as usize][ny as usize].get_is_passable() {
+                walls += 1;
+            }
+        }
+    }
+    walls
+}
+
+fn smooth(map: &mut Map) {
+    let previous = map.clone();
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            map[x as usize][y as usize] =
+                if is_border(x, y) || count_walls_around(x, y, &previous) >= WALL_THRESHOLD {
+                    Tile::wall()
+                } else {
+                    Tile::empty()
+                };
+        }
+    }
+}
+
+// fills every cave pocket except the biggest one and returns the tiles of the biggest
+fn keep_largest_region(map: &mut Map) -> Vec<(i32, i32)> {
+    let mut visited = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut regions = vec![];
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            if visited[x as usize][y as usize] || !map[x as usize][y as usize].get_is_passable() {
+                continue;
+            }
+            let region = Tile::flood_fill(x, y, map);
+            for &(rx, ry) in region.iter() {
+                visited[rx as usize][ry as usize] = true;
+            }
+            regions.push(region);
+        }
+    }
+
+    let largest = regions
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, region)| region.len())
+        .map_or(0, |(index, _)| index);
+    for (index, region) in regions.iter().enumerate() {
+        if index != largest {
+            for &(x, y) in region.iter() {
+                map[x as usize][y as usize] = Tile::wall();
+            }
+        }
+    }
+    if regions.is_empty() {
+        vec![]
+    } else {
+        regions.swap_remove(largest)
+    }
+}
+
+// grows patches of a walkable tile kind around random spots of the cave
+fn scatter_patches(
+    map: &mut Map,
+    region: &[(i32, i32)],
+    kind: TileKind,
+    count: i32,
+    rng: &mut GameRng,
+) {
+    for _ in 0..count {
+        let (x, y) = region[rng.gen_range(0..region.len())];
+        for (px, py) in Tile::flood_fill(x, y, map).into_iter().take(PATCH_SIZE) {
+            map[px as usize][py as usize].set_kind(kind);
+        }
+    }
+}
+
+// like `scatter_patches` for kinds nothing can walk through, a pool that would
+// cut the cave in two is taken back so every floor tile stays reachable
+fn scatter_hazards(
+    map: &mut Map,
+    region: &[(i32, i32)],
+    kind: TileKind,
+    count: i32,
+    rng: &mut GameRng,
+) {
+    for _ in 0..count {
+        let (x, y) = region[rng.gen_range(0..region.len())];
+        let pool: Vec<(i32, i32)> = Tile::flood_fill(x, y, map)
+            .into_iter()
+            .take(HAZARD_SIZE)
+            .collect();
+        let previous: Vec<TileKind> = pool
+            .iter()
+            .map(|&(px, py)| map[px as usize][py as usize].get_kind())
+            .collect();
+        for &(px, py) in pool.iter() {
+            map[px as usize][py as usize].set_kind(kind);
+        }
+
+        let passable: Vec<&(i32, i32)> = region
+            .iter()
+            .filter(|&&(rx, ry)| map[rx as usize][ry as usize].get_is_passable())
+            .collect();
+        let reached = passable
+            .first()
+            .map_or(0, |&&(rx, ry)| Tile::flood_fill(rx, ry, map).len());
+        if reached != passable.len() {
+            for (&(px, py), &kind) in pool.iter().zip(previous.iter()) {
+                map[px as usize][py as usize].set_kind(kind);
+            }
+        }
+    }
+}
+
+pub fn generate_caves(map: &mut Map, entities: &mut Vec<Entity>, rng: &mut GameRng) {
+    let region = loop {
+        random_fill(map, rng);
+        for _ in 0..SMOOTHING_ITERATIONS {
+            smooth(map);
+        }
+        let region = keep_largest_region(map);
+        if region.len() >= MIN_CAVE_SIZE {
+            break region;
+        }
+    };
+    scatter_patches(map, &region, TileKind::Grass, GRASS_PATCHES, rng);
+    scatter_patches(map, &region, TileKind::Water, WATER_POOLS, rng);
+    scatter_patches(map, &region, TileKind::Rubble, RUBBLE_PATCHES, rng);
+    scatter_hazards(map, &region, TileKind::Lava, LAVA_POOLS, rng);
+    scatter_hazards(map, &region, TileKind::Chasm, CHASMS, rng);
+    let region: Vec<(i32, i32)> = region
+        .into_iter()
+        .filter(|&(x, y)| map[x as usize][y as usize].get_is_passable())
+        .collect();
+
+    // the stairs go to the tile that takes the longest walk from the player start
+    let (player_x, player_y) = region[rng.gen_range(0..region.len())];
+    let by_distance = Tile::flood_fill(player_x, player_y, map);
+    let (frederic_x, frederic_y) = by_distance[1];
+    let (stairs_x, stairs_y) = by_distance[by_distance.len() - 1];
+
+    let spawn_region: Vec<(i32, i32)> = region
+        .into_iter()
+        .filter(|&(x, y)| (x - player_x).pow(2) + (y - player_y).pow(2) > SAFE_RADIUS.pow(2))
+        .collect();
+    Entity::populate_region(&spawn_region, map, entities, rng);
+
+    entities.push(Entity::new_stairs(stairs_x, stairs_y, Stairs::Down));
+    entities[PLAYER].set_position(player_x, player_y);
+    entities[FREDERIC].set_position(frederic_x, frederic_y);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::starting_entities, rng};
+
+    #[test]
+    fn caves_have_rough_ground_and_hazards() {
+        let mut found = vec![];
+        for seed in 0..10 {
+            let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+            let mut entities = starting_entities();
+            generate_caves(&mut map, &mut entities, &mut rng::new_rng(seed));
+            found.extend(map.iter().flatten().map(Tile::get_kind));
+
+            // the hazards never cut the cave in two
+            let (x, y) = entities[PLAYER].get_coordinates();
+            let passable = map.iter().flatten().filter(|t| t.get_is_passable()).count();
+            assert_eq!(
+                Tile::flood_fill(x, y, &map).len(),
+                passable,
+                "seed {}",
+                seed
+            );
+        }
+        for kind in [TileKind::Rubble, TileKind::Lava, TileKind::Chasm] {
+            assert!(found.contains(&kind), "no {:?} in any cave", kind);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+// plain RGB color so the game core does not depend on tcod, the values match
+// the libtcod palette so the front-end can convert them one to one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+
+    // blend towards `other`, 0.0 keeps this color and 1.0 gives `other`
+    pub fn lerp(self, other: Color, amount: f32) -> Self {
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+        Color::new(
+            mix(self.r, other.r),
+            mix(self.g, other.g),
+            mix(self.b, other.b),
+        )
+    }
+}
+
+pub const BLACK: Color = Color::new(0, 0, 0);
+pub const WHITE: Color = Color::new(255, 255, 255);
+pub const LIGHT_GREY: Color = Color::new(159, 159, 159);
+
+pub const RED: Color = Color::new(255, 0, 0);
+pub const LIGHT_RED: Color = Color::new(255, 63, 63);
+pub const DARK_RED: Color = Color::new(191, 0, 0);
+pub const DARKER_RED: Color = Color::new(127, 0, 0);
+
+pub const ORANGE: Color = Color::new(255, 127, 0);
+pub const DARKER_ORANGE: Color = Color::new(127, 63, 0);
+pub const YELLOW: Color = Color::new(255, 255, 0);
+pub const GOLD: Color = Color::new(255, 191, 0);
+pub const LIGHT_YELLOW: Color = Color::new(255, 255, 63);
+
+pub const GREEN: Color = Color::new(0, 255, 0);
+pub const DARKER_GREEN: Color = Color::new(0, 127, 0);
+pub const DESATURATED_GREEN: Color = Color::new(63, 127, 63);
+
+pub const LIGHT_BLUE: Color = Color::new(63, 63, 255);
+pub const SKY: Color = Color::new(0, 191, 255);
+
+pub const VIOLET: Color = Color::new(127, 0, 255);
+pub const LIGHT_VIOLET: Color = Color::new(159, 63, 255);
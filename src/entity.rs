@@ -1,28 +1,59 @@
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use tcod::{
-    colors::{
-        self, Color, DARK_RED, GREEN, LIGHT_BLUE, LIGHT_VIOLET, LIGHT_YELLOW, ORANGE, RED, VIOLET,
-        WHITE, YELLOW,
-    },
-    Console, Map as FovMap,
-};
 
 use crate::{
-    menu,
-    message::Messages,
+    color::{
+        self, Color, DARK_RED, GOLD, GREEN, LIGHT_BLUE, LIGHT_VIOLET, LIGHT_YELLOW, ORANGE, RED,
+        VIOLET, WHITE, YELLOW,
+    },
+    equipment::{Equipment, EquipmentKind, EQUIPMENT_KINDS},
+    fov::FovMap,
+    game::{self, Game},
+    message::{Category, Messages},
+    pathfinding::{self, PATH_SEARCH_BUDGET},
+    rng::GameRng,
     room::Room,
-    tile::{Map, Tile, MAP_HEIGHT, MAP_WIDTH},
-    Game, Tcod, LEVEL_SCREEN_WIDTH,
+    status::{Effect, StatusEffect, StatusEffects},
+    tile::{Map, Tile, TileKind, MAP_HEIGHT, MAP_WIDTH},
 };
 
 const MAX_ROOM_MONSTERS: i32 = 3;
 const MAX_ROOM_ITEMS: i32 = 2;
+// open tiles in a region that count as one room when populating caves, higher than
+// the size of a room since room levels also have plenty of empty tunnels
+const TILES_PER_ROOM: i32 = 120;
 const HEAL_AMOUNT: i32 = 4;
+// the healing potion keeps healing a little for a few turns
+const HEAL_REGENERATION_TURNS: u32 = 5;
+const HEAL_REGENERATION_AMOUNT: i32 = 1;
 pub const PLAYER: usize = 0;
 pub const FREDERIC: usize = 1;
 const LIGHTNING_RANGE: i32 = 5;
 const LIGHTNING_DAMAGE: i32 = 20;
+const LIGHTNING_STUN_TURNS: u32 = 2;
+const CONFUSE_RANGE: i32 = 8;
+const CONFUSE_NUM_TURNS: u32 = 10;
+const FIREBALL_RANGE: i32 = 10;
+const FIREBALL_RADIUS: f32 = 3.0;
+const FIREBALL_DAMAGE: i32 = 12;
+// whatever survives the fireball keeps burning, the player is also blinded by the flash
+const FIREBALL_BURN_TURNS: u32 = 3;
+const FIREBALL_BURN_DAMAGE: i32 = 2;
+const FIREBALL_BLIND_TURNS: u32 = 4;
+// chance for a hit that does damage to also give the attacker's `Fighter::on_hit` effect
+const ON_HIT_CHANCE: f64 = 0.25;
+const ORK_POISON: StatusEffect = StatusEffect::new(Effect::Poisoned, 4, 1);
+const TROLL_SLOW: StatusEffect = StatusEffect::new(Effect::Slowed, 3, 0);
+const LEVEL_UP_HASTE_TURNS: u32 = 10;
+// how likely every kind of item is to be found, out of the sum of all of them
+const ITEM_CHANCES: [(Item, u32); 6] = [
+    (Item::Heal, 35),
+    (Item::Lightning, 10),
+    (Item::Confuse, 10),
+    (Item::Fireball, 10),
+    (Item::Teleport, 5),
+    (Item::Equipment, 30),
+];
 pub const LEVEL_UP_BASE: i32 = 200;
 pub const LEVEL_UP_FACTOR: i32 = 150;
 
@@ -66,21 +97,60 @@ pub struct Entity {
     fighter: Option<Fighter>,
     ai: Option<AI>,
     item: Option<Item>,
+    stairs: Option<Stairs>,
     always_visible: bool,
     level: i32,
+    #[serde(default)]
+    equipment: Option<Equipment>,
+    #[serde(default)]
+    effects: StatusEffects,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Item {
     Heal,
     Lightning,
+    // opens one locked door, used up when the door is unlocked
+    Key,
+    // worn for the bonuses in `Entity::equipment`, using it puts it on or takes it off
+    Equipment,
+    Confuse,
+    // hurts everything around the target, the player too
+    Fireball,
+    Teleport,
+}
+
+impl Item {
+    // how far away the target of an item can be, None for items used without a target
+    pub fn target_range(self) -> Option<i32> {
+        match self {
+            Item::Confuse => Some(CONFUSE_RANGE),
+            Item::Fireball => Some(FIREBALL_RANGE),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Stairs {
+    Down,
+    Up,
 }
 
 pub enum UseResult {
     UsedUp,
+    // did something but stays in the inventory, like putting on equipment
+    UsedAndKept,
     Cancelled,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Stat {
+    Constitution,
+    Strength,
+    Agility,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DeathCallback {
     Player,
@@ -105,6 +175,9 @@ pub struct Fighter {
     power: i32,
     xp: i32,
     on_death: DeathCallback,
+    // given to the target of a hit now and then, see `ON_HIT_CHANCE`
+    #[serde(default)]
+    on_hit: Option<StatusEffect>,
 }
 impl Fighter {
     pub fn get_hp(&self) -> (i32, i32) {
@@ -124,10 +197,6 @@ impl Fighter {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum AI {
     Basic,
-    // Confused {
-    //     previous_ai: Box<AI>,
-    //     num_turns: i32,
-    // },
 }
 
 impl Entity {
@@ -143,9 +212,69 @@ impl Entity {
             fighter: None,
             ai: None,
             item: None,
+            stairs: None,
             always_visible: false,
             level: 1,
+            equipment: None,
+            effects: StatusEffects::default(),
+        }
+    }
+
+    pub fn new_stairs(x: i32, y: i32, direction: Stairs) -> Self {
+        let mut stairs = match direction {
+            Stairs::Down => Entity::new(x, y, '>', WHITE, "stairs down", false),
+            Stairs::Up => Entity::new(x, y, '<', WHITE, "stairs up", false),
+        };
+        stairs.stairs = Some(direction);
+        stairs.make_always_visible();
+        stairs
+    }
+
+    pub fn new_key(x: i32, y: i32) -> Self {
+        let mut key = Entity::new(x, y, '-', GOLD, "key", false);
+        key.item = Some(Item::Key);
+        key.make_always_visible();
+        key
+    }
+
+    // a random piece of equipment for `Item::Equipment`
+    pub fn new_item(x: i32, y: i32, item: Item, rng: &mut GameRng) -> Self {
+        let (char, color, name) = match item {
+            Item::Heal => ('!', VIOLET, "healing potion"),
+            Item::Lightning => ('#', LIGHT_YELLOW, "scroll of lightning bolt"),
+            Item::Confuse => ('#', LIGHT_VIOLET, "scroll of confusion"),
+            Item::Fireball => ('#', ORANGE, "scroll of fireball"),
+            Item::Teleport => ('#', color::SKY, "scroll of teleportation"),
+            Item::Key => return Entity::new_key(x, y),
+            Item::Equipment => {
+                let kind = &EQUIPMENT_KINDS[rng.gen_range(0..EQUIPMENT_KINDS.len())];
+                return Entity::new_equipment(x, y, kind);
+            }
+        };
+        let mut entity = Entity::new(x, y, char, color, name, false);
+        entity.item = Some(item);
+        entity.make_always_visible();
+        entity
+    }
+
+    fn random_item(rng: &mut GameRng) -> Item {
+        let total: u32 = ITEM_CHANCES.iter().map(|(_, chance)| chance).sum();
+        let mut roll = rng.gen_range(0..total);
+        for (item, chance) in ITEM_CHANCES {
+            if roll < chance {
+                return item;
+            }
+            roll -= chance;
         }
+        Item::Heal
+    }
+
+    pub fn new_equipment(x: i32, y: i32, kind: &EquipmentKind) -> Self {
+        let mut item = Entity::new(x, y, kind.glyph, kind.color, kind.name, false);
+        item.item = Some(Item::Equipment);
+        item.equipment = Some(kind.equipment());
+        item.make_always_visible();
+        item
     }
 
     pub fn move_by(id: usize, dx: i32, dy: i32, map: &Map, entities: &mut [Entity]) {
@@ -176,6 +305,29 @@ impl Entity {
         Entity::move_by(id, dx, dy, map, entities);
     }
 
+    // walks around walls and other monsters, if there is no path within
+    // the search budget it falls back to heading straight for the target
+    pub fn move_astar(id: usize, target_x: i32, target_y: i32, map: &Map, entities: &mut [Entity]) {
+        let from = entities[id].get_coordinates();
+        let path = pathfinding::find_path(
+            from,
+            (target_x, target_y),
+            map,
+            entities,
+            PATH_SEARCH_BUDGET,
+        );
+        match path.and_then(|path| path.first().copied()) {
+            Some((x, y)) if !Tile::is_blocked(x, y, map, entities) => {
+                entities[id].set_position(x, y);
+            }
+            _ => Entity::move_towards(id, target_x, target_y, map, entities),
+        }
+    }
+
+    pub fn distance(&self, x: i32, y: i32) -> f32 {
+        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
+    }
+
     pub fn distance_to(&self, other: &Entity) -> f32 {
         let dx = other.x - self.x;
         let dy = other.y - self.y;
@@ -193,15 +345,37 @@ impl Entity {
         }
     }
 
-    pub fn ai_take_turn(monster_id: usize, fov: &FovMap, game: &mut Game, entities: &mut [Entity]) {
+    pub fn ai_take_turn(monster_id: usize, game: &mut Game, entities: &mut [Entity]) {
+        if entities[monster_id].has_effect(Effect::Stunned) {
+            return;
+        }
+        if entities[monster_id].has_effect(Effect::Confused) {
+            let (dx, dy) = Entity::random_direction(game);
+            Entity::move_by(monster_id, dx, dy, &game.map, entities);
+            return;
+        }
         let (m_x, m_y) = entities[monster_id].get_coordinates();
-        if fov.is_in_fov(m_x, m_y) {
+        if game.fov.is_in_fov(m_x, m_y) {
             if entities[monster_id].distance_to(&entities[PLAYER]) >= 2.0 {
+                // a blind monster only notices the player right next to it
+                if entities[monster_id].has_effect(Effect::Blind) {
+                    return;
+                }
                 let (p_x, p_y) = entities[PLAYER].get_coordinates();
-                Entity::move_towards(monster_id, p_x, p_y, &game.map, entities);
+                Entity::move_astar(monster_id, p_x, p_y, &game.map, entities);
             } else if entities[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
                 let (monster, player) = Entity::mut_two(monster_id, PLAYER, entities);
-                monster.attack(player, &mut game.messages);
+                monster.attack(player, game);
+            }
+        }
+    }
+
+    // one of the 8 directions, never standing still
+    pub fn random_direction(game: &mut Game) -> (i32, i32) {
+        loop {
+            let direction = (game.rng.gen_range(-1..=1), game.rng.gen_range(-1..=1));
+            if direction != (0, 0) {
+                return direction;
             }
         }
     }
@@ -213,29 +387,72 @@ impl Entity {
         game: &mut Game,
         entities: &mut [Entity],
     ) {
-        if !entities[PLAYER].is_alive() {
+        // standing still just passes the turn
+        if !entities[PLAYER].is_alive() || (dx, dy) == (0, 0) {
             return;
         }
         let (mut x, mut y) = entities[PLAYER].get_coordinates();
         x = x + dx;
         y = y + dy;
+        if x < 0 || y < 0 || x >= MAP_WIDTH || y >= MAP_HEIGHT {
+            return;
+        }
 
         let target_id = entities
             .iter()
             .position(|entity| entity.fighter.is_some() && entity.get_coordinates() == (x, y));
 
+        match (target_id, game.map[x as usize][y as usize].get_kind()) {
+            (Some(target_id), _) => {
+                let (player, target) = Entity::mut_two(PLAYER, target_id, entities);
+                player.attack(target, game);
+            }
+            // walking into a door opens it instead of moving
+            (None, TileKind::DoorClosed) => {
+                game.set_tile_kind(x, y, TileKind::DoorOpen);
+                game.compute_fov(entities);
+                game.messages
+                    .add("You open the door.", WHITE, Category::Level);
+            }
+            (None, TileKind::DoorLocked) => Entity::unlock_door(x, y, game, entities),
+            (None, _) => Entity::move_by(PLAYER, dx, dy, &game.map, entities),
+        }
+    }
+
+    // attacks the fighter next to the player without moving, false if there is none
+    pub fn player_attack(dx: i32, dy: i32, game: &mut Game, entities: &mut [Entity]) -> bool {
+        let (x, y) = entities[PLAYER].get_coordinates();
+        let target_id = entities.iter().enumerate().position(|(id, entity)| {
+            id != PLAYER && entity.fighter.is_some() && entity.get_coordinates() == (x + dx, y + dy)
+        });
         match target_id {
             Some(target_id) => {
                 let (player, target) = Entity::mut_two(PLAYER, target_id, entities);
-                player.attack(target, &mut game.messages);
+                player.attack(target, game);
+                true
             }
-            None => Entity::move_by(PLAYER, dx, dy, &game.map, entities),
+            None => false,
         }
     }
 
-    pub fn draw(&self, con: &mut dyn Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, tcod::BackgroundFlag::None)
+    // a locked door opens with any key from the inventory, the key is used up
+    fn unlock_door(x: i32, y: i32, game: &mut Game, entities: &[Entity]) {
+        let key_id = game
+            .inventory
+            .iter()
+            .position(|item| item.get_item() == Some(Item::Key));
+        match key_id {
+            Some(key_id) => {
+                game.inventory.remove(key_id);
+                game.set_tile_kind(x, y, TileKind::DoorOpen);
+                game.compute_fov(entities);
+                game.messages
+                    .add("You unlock the door with your key.", GOLD, Category::Level);
+            }
+            None => game
+                .messages
+                .add("The door is locked.", WHITE, Category::Level),
+        }
     }
 
     pub fn make_always_visible(&mut self) {
@@ -253,6 +470,12 @@ impl Entity {
         self.x = x;
         self.y = y;
     }
+    pub fn get_char(&self) -> char {
+        self.char
+    }
+    pub fn get_color(&self) -> Color {
+        self.color
+    }
     pub fn get_is_blocks(&self) -> bool {
         self.blocks
     }
@@ -262,6 +485,9 @@ impl Entity {
     pub fn get_item(&self) -> Option<Item> {
         self.item
     }
+    pub fn get_stairs(&self) -> Option<Stairs> {
+        self.stairs
+    }
     pub fn get_level(&self) -> i32 {
         self.level
     }
@@ -270,12 +496,13 @@ impl Entity {
     }
     pub fn kill(&mut self) {
         self.alive = false;
+        self.effects.clear();
         self.char = '%';
         self.color = DARK_RED;
         self.name = format!("remains of {}", self.name);
     }
     fn player_death(player: &mut Entity, messages: &mut Messages) {
-        messages.add("You died!", RED);
+        messages.add("You died!", RED, Category::Combat);
 
         player.kill();
     }
@@ -287,6 +514,7 @@ impl Entity {
                 monster.fighter.unwrap().xp
             ),
             ORANGE,
+            Category::Combat,
         );
 
         monster.kill();
@@ -297,6 +525,10 @@ impl Entity {
     pub fn is_alive(&self) -> bool {
         self.alive
     }
+    fn is_player(&self) -> bool {
+        self.fighter
+            .is_some_and(|f| f.on_death == DeathCallback::Player)
+    }
     pub fn take_damage(&mut self, damage: i32, messages: &mut Messages) -> Option<i32> {
         if let Some(fighter) = self.fighter.as_mut() {
             if damage > 0 {
@@ -313,39 +545,88 @@ impl Entity {
         }
         None
     }
-    pub fn attack(&mut self, target: &mut Entity, messages: &mut Messages) {
-        let damage = self.fighter.map_or(0, |f| f.power) - target.fighter.map_or(0, |f| f.defense);
+    pub fn attack(&mut self, target: &mut Entity, game: &mut Game) {
+        let damage = self.power(game) - target.defense(game);
         if damage > 0 {
-            messages.add(
+            game.messages.add(
                 format!(
                     "{0} attacks {1} for {2} hit points.",
                     self.name, target.name, damage
                 ),
                 WHITE,
+                Category::Combat,
             );
-            if let Some(xp) = target.take_damage(damage, messages) {
+            // the name changes once the target turns into remains
+            let target_name = target.name.clone();
+            if let Some(xp) = target.take_damage(damage, &mut game.messages) {
                 self.fighter.as_mut().unwrap().xp += xp;
+                if target.is_player() {
+                    game.history.killed_by(&self.name);
+                } else {
+                    game.history.record_kill(&target_name);
+                }
+            } else if let Some(status) = self.fighter.and_then(|f| f.on_hit) {
+                if game.rng.gen_bool(ON_HIT_CHANCE) {
+                    target.add_effect(status, game);
+                }
             }
         } else {
-            messages.add(
+            game.messages.add(
                 format!(
                     "{0} attacks {1} but it has no effect!",
                     self.name, target.name
                 ),
                 WHITE,
+                Category::Combat,
             );
         }
     }
 
-    pub fn heal(&mut self, amount: i32) {
+    pub fn heal(&mut self, amount: i32, game: &Game) {
+        let max_hp = self.max_hp(game);
         if let Some(ref mut fighter) = self.fighter {
             fighter.hp += amount;
-            if fighter.hp > fighter.max_hp {
-                fighter.hp = fighter.max_hp;
+            if fighter.hp > max_hp {
+                fighter.hp = max_hp;
             }
         }
     }
 
+    // the items the entity wears, only the player can wear anything and it
+    // keeps its equipment in the inventory
+    pub fn equipped<'a>(&self, game: &'a Game) -> Vec<&'a Entity> {
+        if !self.is_player() {
+            return vec![];
+        }
+        game.inventory
+            .iter()
+            .filter(|item| item.equipment.is_some_and(|e| e.equipped))
+            .collect()
+    }
+
+    fn equipment_bonus(&self, game: &Game, bonus: fn(&Equipment) -> i32) -> i32 {
+        self.equipped(game)
+            .iter()
+            .filter_map(|item| item.equipment.as_ref())
+            .map(bonus)
+            .sum()
+    }
+
+    // the stats with the bonuses of the equipment, `Fighter` only has the base values
+    pub fn power(&self, game: &Game) -> i32 {
+        self.fighter.map_or(0, |f| f.power) + self.equipment_bonus(game, |e| e.power_bonus)
+    }
+    pub fn defense(&self, game: &Game) -> i32 {
+        self.fighter.map_or(0, |f| f.defense) + self.equipment_bonus(game, |e| e.defense_bonus)
+    }
+    pub fn max_hp(&self, game: &Game) -> i32 {
+        self.fighter.map_or(0, |f| f.max_hp) + self.equipment_bonus(game, |e| e.max_hp_bonus)
+    }
+
+    pub fn get_equipment(&self) -> Option<Equipment> {
+        self.equipment
+    }
+
     pub fn make_fighter(
         &mut self,
         max_hp: i32,
@@ -362,8 +643,14 @@ impl Entity {
             power,
             xp,
             on_death,
+            on_hit: None,
         })
     }
+    pub fn set_on_hit(&mut self, status: StatusEffect) {
+        if let Some(fighter) = self.fighter.as_mut() {
+            fighter.on_hit = Some(status);
+        }
+    }
     pub fn get_fighter(&self) -> Option<Fighter> {
         self.fighter
     }
@@ -372,24 +659,58 @@ impl Entity {
         self.ai = Some(AI::Basic);
     }
 
-    pub fn populate_room(room: &mut Room, map: &Map, entities: &mut Vec<Entity>) {
-        let num_monsters = rand::thread_rng().gen_range(0..=MAX_ROOM_MONSTERS);
+    pub fn populate_room(
+        room: &mut Room,
+        map: &Map,
+        entities: &mut Vec<Entity>,
+        rng: &mut GameRng,
+    ) {
         let (x1, x2, y1, y2) = room.get_room_coordinates();
+        Entity::populate(map, entities, rng, |rng| {
+            (rng.gen_range(x1 + 1..x2), rng.gen_range(y1 + 1..y2))
+        });
+    }
+
+    // same as `populate_room` for open areas without a rectangular shape (caves),
+    // a region gets as many monsters and items as a room of similar size would
+    pub fn populate_region(
+        region: &[(i32, i32)],
+        map: &Map,
+        entities: &mut Vec<Entity>,
+        rng: &mut GameRng,
+    ) {
+        if region.is_empty() {
+            return;
+        }
+        let rooms_worth = (region.len() as i32 / TILES_PER_ROOM).max(1);
+        for _ in 0..rooms_worth {
+            Entity::populate(map, entities, rng, |rng| {
+                region[rng.gen_range(0..region.len())]
+            });
+        }
+    }
+
+    fn populate<F>(map: &Map, entities: &mut Vec<Entity>, rng: &mut GameRng, mut random_position: F)
+    where
+        F: FnMut(&mut GameRng) -> (i32, i32),
+    {
+        let num_monsters = rng.gen_range(0..=MAX_ROOM_MONSTERS);
         for _ in 0..num_monsters {
-            let x = rand::thread_rng().gen_range(x1 + 1..x2);
-            let y = rand::thread_rng().gen_range(y1 + 1..y2);
+            let (x, y) = random_position(rng);
 
-            let do_generate_ork = rand::random::<f32>() < 0.8;
+            let do_generate_ork = rng.gen::<f32>() < 0.8;
             if !Tile::is_blocked(x, y, map, entities) {
                 let mut monster = if do_generate_ork {
-                    let mut ork = Entity::new(x, y, 'o', colors::DESATURATED_GREEN, "Ork", true);
+                    let mut ork = Entity::new(x, y, 'o', color::DESATURATED_GREEN, "Ork", true);
                     ork.make_fighter(10, 10, 0, 3, 35, DeathCallback::Monster);
+                    ork.set_on_hit(ORK_POISON);
                     ork.set_ai();
                     ork
                 // generate ORK
                 } else {
-                    let mut troll = Entity::new(x, y, 'T', colors::DARKER_GREEN, "Troll", true); // gen TROLL
+                    let mut troll = Entity::new(x, y, 'T', color::DARKER_GREEN, "Troll", true); // gen TROLL
                     troll.make_fighter(16, 16, 1, 4, 100, DeathCallback::Monster);
+                    troll.set_on_hit(TROLL_SLOW);
                     troll.set_ai();
                     troll
                 };
@@ -399,45 +720,145 @@ impl Entity {
         }
 
         // add items to the room
-        let num_items = rand::thread_rng().gen_range(0..=MAX_ROOM_ITEMS);
+        let num_items = rng.gen_range(0..=MAX_ROOM_ITEMS);
 
         for _ in 0..num_items {
-            let x = rand::thread_rng().gen_range(x1 + 1..x2);
-            let y = rand::thread_rng().gen_range(y1 + 1..y2);
+            let (x, y) = random_position(rng);
 
             if !Tile::is_blocked(x, y, map, entities) {
-                let dice = rand::random::<f32>();
-                let mut item = if dice < 0.7 {
-                    let mut item = Entity::new(x, y, '!', VIOLET, "healing potion", false);
-                    item.item = Some(Item::Heal);
-                    item
-                } else {
-                    let mut item =
-                        Entity::new(x, y, '#', LIGHT_YELLOW, "scroll of lightning bolt", false);
-                    item.item = Some(Item::Lightning);
-                    item
-                };
-                item.make_always_visible();
-                entities.push(item);
+                let item = Entity::random_item(rng);
+                entities.push(Entity::new_item(x, y, item, rng));
             }
         }
     }
 
-    pub fn mobs_turn(
-        game: &mut Game,
-        fov: &FovMap,
-        entities: &mut [Entity],
-        player_took_turn: bool,
-    ) {
+    pub fn mobs_turn(game: &mut Game, entities: &mut [Entity], player_took_turn: bool) {
         if entities[PLAYER].is_alive() && player_took_turn {
             for id in 0..entities.len() {
-                if entities[id].ai.is_some() {
-                    Entity::ai_take_turn(id, fov, game, entities);
+                for _ in 0..Entity::monster_actions(id, game.turn, entities) {
+                    if entities[id].ai.is_none() || !entities[PLAYER].is_alive() {
+                        break;
+                    }
+                    Entity::ai_take_turn(id, game, entities);
                 }
             }
         }
     }
 
+    // how often the entity acts in a turn, a slowed one only every other turn
+    fn actions_per_turn(&self, turn: u32) -> u32 {
+        if self.has_effect(Effect::Hasted) {
+            2
+        } else if self.has_effect(Effect::Slowed) {
+            turn % 2
+        } else {
+            1
+        }
+    }
+
+    // a hasted player gets every other turn for free, a slowed one gives the monsters two
+    fn monster_actions(monster_id: usize, turn: u32, entities: &[Entity]) -> u32 {
+        let player = &entities[PLAYER];
+        if player.has_effect(Effect::Hasted) && turn % 2 == 1 {
+            return 0;
+        }
+        let actions = entities[monster_id].actions_per_turn(turn);
+        if player.has_effect(Effect::Slowed) {
+            actions * 2
+        } else {
+            actions
+        }
+    }
+
+    pub fn get_effects(&self) -> &StatusEffects {
+        &self.effects
+    }
+
+    pub fn has_effect(&self, effect: Effect) -> bool {
+        self.effects.has(effect)
+    }
+
+    // only the player and the monsters in view get a message
+    pub fn add_effect(&mut self, status: StatusEffect, game: &mut Game) {
+        let effect = status.get_effect();
+        let text = match self.effects.add(status) {
+            Some(cancelled) => self.no_longer_text(cancelled),
+            None if self.is_player() => format!("You are {}!", effect.name()),
+            None => format!("{} is {}!", self.name, effect.name()),
+        };
+        if self.is_player() || game.fov.is_in_fov(self.x, self.y) {
+            game.messages.add(text, effect.color(), Category::Combat);
+        }
+    }
+
+    fn no_longer_text(&self, effect: Effect) -> String {
+        if self.is_player() {
+            format!("You are no longer {}.", effect.name())
+        } else {
+            format!("{} is no longer {}.", self.name, effect.name())
+        }
+    }
+
+    // once a turn after the monsters moved, the damage and healing first and
+    // then the effects that ran out are removed
+    pub fn tick_effects(game: &mut Game, entities: &mut [Entity]) {
+        for id in 0..entities.len() {
+            let statuses: Vec<StatusEffect> = entities[id].effects.iter().copied().collect();
+            for status in statuses {
+                if !entities[id].is_alive() {
+                    break;
+                }
+                match status.get_effect() {
+                    Effect::Poisoned | Effect::Burning => {
+                        Entity::effect_damage(id, status, game, entities)
+                    }
+                    Effect::Regenerating => entities[id].heal(status.get_strength(), game),
+                    _ => {}
+                }
+            }
+            for effect in entities[id].effects.tick() {
+                let entity = &entities[id];
+                if entity.is_player() || game.fov.is_in_fov(entity.x, entity.y) {
+                    let text = entity.no_longer_text(effect);
+                    game.messages.add(text, effect.color(), Category::Combat);
+                }
+            }
+        }
+    }
+
+    // monsters that die from an effect count as the player's kill
+    fn effect_damage(id: usize, status: StatusEffect, game: &mut Game, entities: &mut [Entity]) {
+        let effect = status.get_effect();
+        let damage = status.get_strength();
+        let entity = &entities[id];
+        if entity.is_player() || game.fov.is_in_fov(entity.x, entity.y) {
+            let text = if entity.is_player() {
+                format!("You lose {} hit points, you are {}.", damage, effect.name())
+            } else {
+                format!(
+                    "{} loses {} hit points, it is {}.",
+                    entity.name,
+                    damage,
+                    effect.name()
+                )
+            };
+            game.messages.add(text, effect.color(), Category::Combat);
+        }
+        let name = entities[id].get_name();
+        let is_player = entities[id].is_player();
+        if let Some(xp) = entities[id].take_damage(damage, &mut game.messages) {
+            if is_player {
+                let cause = effect.death_cause().unwrap_or("killed");
+                game.history.record_death(cause.to_string(), None);
+            } else {
+                if let Some(fighter) = entities[PLAYER].fighter.as_mut() {
+                    fighter.xp += xp;
+                }
+                game.history.record_kill(&name);
+            }
+        }
+    }
+
     pub fn pick_item_up(id: usize, game: &mut Game, entities: &mut Vec<Entity>) {
         if game.inventory.len() >= 26 {
             game.messages.add(
@@ -446,49 +867,138 @@ impl Entity {
                     entities[id].get_name()
                 ),
                 RED,
+                Category::Item,
             );
         } else {
             let item = entities.swap_remove(id);
-            game.messages
-                .add(format!("You picked up a {}", item.get_name()), GREEN);
+            game.messages.add(
+                format!("You picked up a {}", item.get_name()),
+                GREEN,
+                Category::Item,
+            );
             game.inventory.push(item);
         }
     }
 
     pub fn drop_item(inventory_id: usize, game: &mut Game, entities: &mut Vec<Entity>) {
+        if game.inventory[inventory_id]
+            .equipment
+            .is_some_and(|e| e.equipped)
+        {
+            Entity::unequip(inventory_id, game, entities);
+        }
         let mut item = game.inventory.remove(inventory_id);
         item.set_position(entities[PLAYER].x, entities[PLAYER].y);
-        game.messages
-            .add(format!("You dropped a {}", item.name), YELLOW);
+        game.messages.add(
+            format!("You dropped a {}", item.name),
+            YELLOW,
+            Category::Item,
+        );
         entities.push(item);
     }
 
     pub fn cast_heal(
         _inventory_id: usize,
-        _tcod: &Tcod,
+        _target: Option<(i32, i32)>,
         game: &mut Game,
         entities: &mut [Entity],
     ) -> UseResult {
         if let Some(fighter) = entities[PLAYER].fighter {
-            if fighter.hp == fighter.max_hp {
-                game.messages.add("You are already at full health", RED);
+            if fighter.hp >= entities[PLAYER].max_hp(game) {
+                game.messages
+                    .add("You are already at full health", RED, Category::Item);
                 return UseResult::Cancelled;
             }
-            game.messages
-                .add("Your wounds start to feel better!", LIGHT_VIOLET);
-            entities[PLAYER].heal(HEAL_AMOUNT);
+            game.messages.add(
+                "Your wounds start to feel better!",
+                LIGHT_VIOLET,
+                Category::Item,
+            );
+            entities[PLAYER].heal(HEAL_AMOUNT, game);
+            let status = StatusEffect::new(
+                Effect::Regenerating,
+                HEAL_REGENERATION_TURNS,
+                HEAL_REGENERATION_AMOUNT,
+            );
+            entities[PLAYER].add_effect(status, game);
             return UseResult::UsedUp;
         }
         UseResult::Cancelled
     }
 
+    // puts the item on, whatever was in its slot before is taken off
+    pub fn toggle_equipment(
+        inventory_id: usize,
+        _target: Option<(i32, i32)>,
+        game: &mut Game,
+        entities: &mut [Entity],
+    ) -> UseResult {
+        let Some(equipment) = game.inventory[inventory_id].equipment else {
+            return UseResult::Cancelled;
+        };
+        if equipment.equipped {
+            Entity::unequip(inventory_id, game, entities);
+            return UseResult::UsedAndKept;
+        }
+        let worn_id = game.inventory.iter().position(|item| {
+            item.equipment
+                .is_some_and(|e| e.equipped && e.slot == equipment.slot)
+        });
+        if let Some(worn_id) = worn_id {
+            Entity::unequip(worn_id, game, entities);
+        }
+        if let Some(equipment) = game.inventory[inventory_id].equipment.as_mut() {
+            equipment.equipped = true;
+        }
+        game.messages.add(
+            format!(
+                "You equip the {} as your {}.",
+                game.inventory[inventory_id].name,
+                equipment.slot.name()
+            ),
+            GREEN,
+            Category::Item,
+        );
+        UseResult::UsedAndKept
+    }
+
+    // taking off something with a health bonus can leave the player with less health
+    fn unequip(inventory_id: usize, game: &mut Game, entities: &mut [Entity]) {
+        if let Some(equipment) = game.inventory[inventory_id].equipment.as_mut() {
+            equipment.equipped = false;
+        }
+        game.messages.add(
+            format!("You take off the {}.", game.inventory[inventory_id].name),
+            LIGHT_YELLOW,
+            Category::Item,
+        );
+        let max_hp = entities[PLAYER].max_hp(game);
+        if let Some(fighter) = entities[PLAYER].fighter.as_mut() {
+            fighter.hp = fighter.hp.min(max_hp);
+        }
+    }
+
+    pub fn use_key(
+        _inventory_id: usize,
+        _target: Option<(i32, i32)>,
+        game: &mut Game,
+        _entities: &mut [Entity],
+    ) -> UseResult {
+        game.messages.add(
+            "Walk into a locked door to open it with the key.",
+            WHITE,
+            Category::Item,
+        );
+        UseResult::Cancelled
+    }
+
     pub fn cast_lightning(
         _inventory_id: usize,
-        tcod: &Tcod,
+        _target: Option<(i32, i32)>,
         game: &mut Game,
         entities: &mut [Entity],
     ) -> UseResult {
-        let monster_id = Entity::closest_monster(tcod, entities, LIGHTNING_RANGE);
+        let monster_id = Entity::closest_monster(&game.fov, entities, LIGHTNING_RANGE);
         if let Some(monster_id) = monster_id {
             game.messages.add(
                 format!(
@@ -497,21 +1007,167 @@ impl Entity {
                     entities[monster_id].name, LIGHTNING_DAMAGE
                 ),
                 LIGHT_BLUE,
+                Category::Combat,
             );
 
+            let monster_name = entities[monster_id].get_name();
             if let Some(xp) = entities[monster_id].take_damage(LIGHTNING_DAMAGE, &mut game.messages)
             {
                 entities[PLAYER].fighter.as_mut().unwrap().xp += xp;
-            };
+                game.history.record_kill(&monster_name);
+            } else {
+                let status = StatusEffect::new(Effect::Stunned, LIGHTNING_STUN_TURNS, 0);
+                entities[monster_id].add_effect(status, game);
+            }
             UseResult::UsedUp
         } else {
             game.messages
-                .add("No enemy is close enough to strike.", RED);
+                .add("No enemy is close enough to strike.", RED, Category::Item);
             UseResult::Cancelled
         }
     }
 
-    pub fn closest_monster(tcod: &Tcod, entities: &[Entity], max_range: i32) -> Option<usize> {
+    // the target if the player picked one it can see within range of the item
+    fn check_target(
+        target: Option<(i32, i32)>,
+        max_range: i32,
+        game: &mut Game,
+        entities: &[Entity],
+    ) -> Option<(i32, i32)> {
+        let Some((x, y)) = target else {
+            game.messages
+                .add("This needs a target.", RED, Category::Item);
+            return None;
+        };
+        if !game::can_target(x, y, max_range, game, entities) {
+            game.messages
+                .add("That is out of range.", RED, Category::Item);
+            return None;
+        }
+        Some((x, y))
+    }
+
+    pub fn cast_confuse(
+        _inventory_id: usize,
+        target: Option<(i32, i32)>,
+        game: &mut Game,
+        entities: &mut [Entity],
+    ) -> UseResult {
+        let Some((x, y)) = Entity::check_target(target, CONFUSE_RANGE, game, entities) else {
+            return UseResult::Cancelled;
+        };
+        let monster_id = entities.iter().position(|entity| {
+            entity.ai.is_some() && entity.fighter.is_some() && entity.get_coordinates() == (x, y)
+        });
+        match monster_id {
+            Some(monster_id) => {
+                game.messages.add(
+                    format!(
+                        "The eyes of the {} look vacant, as it starts to stumble around!",
+                        entities[monster_id].name
+                    ),
+                    LIGHT_VIOLET,
+                    Category::Combat,
+                );
+                let status = StatusEffect::new(Effect::Confused, CONFUSE_NUM_TURNS, 0);
+                entities[monster_id].add_effect(status, game);
+                UseResult::UsedUp
+            }
+            None => {
+                game.messages
+                    .add("There is no monster there.", RED, Category::Item);
+                UseResult::Cancelled
+            }
+        }
+    }
+
+    // everything around the target gets hurt, the player included if it stands too close
+    pub fn cast_fireball(
+        _inventory_id: usize,
+        target: Option<(i32, i32)>,
+        game: &mut Game,
+        entities: &mut [Entity],
+    ) -> UseResult {
+        let Some((x, y)) = Entity::check_target(target, FIREBALL_RANGE, game, entities) else {
+            return UseResult::Cancelled;
+        };
+        game.messages.add(
+            format!(
+                "The fireball explodes, burning everything within {} tiles!",
+                FIREBALL_RADIUS
+            ),
+            ORANGE,
+            Category::Combat,
+        );
+        for id in 0..entities.len() {
+            let entity = &entities[id];
+            if entity.fighter.is_none()
+                || !entity.is_alive()
+                || entity.distance(x, y) > FIREBALL_RADIUS
+            {
+                continue;
+            }
+            let is_player = entity.is_player();
+            let name = entity.get_name();
+            let text = if is_player {
+                format!("You get burned for {} hit points.", FIREBALL_DAMAGE)
+            } else {
+                format!(
+                    "The {} gets burned for {} hit points.",
+                    name, FIREBALL_DAMAGE
+                )
+            };
+            game.messages.add(text, ORANGE, Category::Combat);
+            if let Some(xp) = entities[id].take_damage(FIREBALL_DAMAGE, &mut game.messages) {
+                if is_player {
+                    let cause = "killed by your own fireball".to_string();
+                    game.history.record_death(cause, None);
+                } else {
+                    if let Some(fighter) = entities[PLAYER].fighter.as_mut() {
+                        fighter.xp += xp;
+                    }
+                    game.history.record_kill(&name);
+                }
+                continue;
+            }
+            let burning =
+                StatusEffect::new(Effect::Burning, FIREBALL_BURN_TURNS, FIREBALL_BURN_DAMAGE);
+            entities[id].add_effect(burning, game);
+            if is_player {
+                let blind = StatusEffect::new(Effect::Blind, FIREBALL_BLIND_TURNS, 0);
+                entities[id].add_effect(blind, game);
+            }
+        }
+        UseResult::UsedUp
+    }
+
+    // lands anywhere that is free, explored or not
+    pub fn cast_teleport(
+        _inventory_id: usize,
+        _target: Option<(i32, i32)>,
+        game: &mut Game,
+        entities: &mut [Entity],
+    ) -> UseResult {
+        let free: Vec<(i32, i32)> = (0..MAP_WIDTH)
+            .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+            .filter(|&(x, y)| !Tile::is_blocked(x, y, &game.map, entities))
+            .collect();
+        if free.is_empty() {
+            game.messages
+                .add("There is nowhere to go.", RED, Category::Item);
+            return UseResult::Cancelled;
+        }
+        let (x, y) = free[game.rng.gen_range(0..free.len())];
+        entities[PLAYER].set_position(x, y);
+        game.messages.add(
+            "You feel yourself pulled through space!",
+            color::SKY,
+            Category::Item,
+        );
+        UseResult::UsedUp
+    }
+
+    pub fn closest_monster(fov: &FovMap, entities: &[Entity], max_range: i32) -> Option<usize> {
         let mut closest_enemy = None;
         let mut closest_dist = (max_range + 1) as f32;
 
@@ -519,7 +1175,7 @@ impl Entity {
             if (id != PLAYER)
                 && entity.fighter.is_some()
                 && entity.ai.is_some()
-                && tcod.fov.is_in_fov(entity.x, entity.y)
+                && fov.is_in_fov(entity.x, entity.y)
             {
                 let dist = entities[PLAYER].distance_to(entity);
                 if dist < closest_dist {
@@ -532,47 +1188,45 @@ impl Entity {
         closest_enemy
     }
 
-    pub fn level_up(tcod: &mut Tcod, game: &mut Game, entities: &mut [Entity]) {
-        let player = &mut entities[PLAYER];
-        let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
+    pub fn level_up_xp(&self) -> i32 {
+        LEVEL_UP_BASE + self.level * LEVEL_UP_FACTOR
+    }
 
-        if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
-            player.level += 1;
-            game.messages.add(
-                format!(
-                    "Your battle skills grow stronger! You reached level {level}!",
-                    level = player.level
-                ),
-                YELLOW,
-            );
-            let fighter = player.fighter.as_mut().unwrap();
-            let mut choice = None;
-            while choice.is_none() {
-                choice = menu(
-                    "Level up! Choose a stat to raise:\n",
-                    &[
-                        format!("Constitution (+20 HP, from {})", fighter.max_hp),
-                        format!("Strength (+1 attach, from {})", fighter.power),
-                        format!("Agility (+1 defence, from {})", fighter.defense),
-                    ],
-                    LEVEL_SCREEN_WIDTH,
-                    &mut tcod.root,
-                );
+    pub fn can_level_up(&self) -> bool {
+        self.fighter.as_ref().map_or(0, |f| f.xp) >= self.level_up_xp()
+    }
+
+    // the front-end asks the player which stat to raise once `can_level_up` is true
+    pub fn level_up(stat: Stat, game: &mut Game, entities: &mut [Entity]) {
+        let player = &mut entities[PLAYER];
+        if !player.can_level_up() {
+            return;
+        }
+        let level_up_xp = player.level_up_xp();
+        player.level += 1;
+        game.messages.add(
+            format!(
+                "Your battle skills grow stronger! You reached level {level}!",
+                level = player.level
+            ),
+            YELLOW,
+            Category::Level,
+        );
+        let fighter = player.fighter.as_mut().unwrap();
+        fighter.xp -= level_up_xp;
+        match stat {
+            Stat::Constitution => {
+                fighter.max_hp += 20;
+                fighter.hp += 20;
             }
-            fighter.xp -= level_up_xp;
-            match choice.unwrap() {
-                0 => {
-                    fighter.max_hp += 20;
-                    fighter.hp += 20;
-                }
-                1 => {
-                    fighter.power += 1;
-                }
-                2 => {
-                    fighter.defense += 1;
-                }
-                _ => unreachable!(),
+            Stat::Strength => {
+                fighter.power += 1;
+            }
+            Stat::Agility => {
+                fighter.defense += 1;
             }
         }
+        let status = StatusEffect::new(Effect::Hasted, LEVEL_UP_HASTE_TURNS, 0);
+        entities[PLAYER].add_effect(status, game);
     }
 }
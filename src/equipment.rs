@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+use crate::color::{self, Color};
+
+// where an item is worn, only one item fits in each slot
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Slot {
+    Weapon,
+    Armor,
+    Shield,
+    Helmet,
+    Ring,
+    Amulet,
+}
+
+impl Slot {
+    pub const ALL: [Slot; 6] = [
+        Slot::Weapon,
+        Slot::Armor,
+        Slot::Shield,
+        Slot::Helmet,
+        Slot::Ring,
+        Slot::Amulet,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Slot::Weapon => "weapon",
+            Slot::Armor => "armor",
+            Slot::Shield => "shield",
+            Slot::Helmet => "helmet",
+            Slot::Ring => "ring",
+            Slot::Amulet => "amulet",
+        }
+    }
+}
+
+// the bonuses an item gives while it is equipped, the base `Fighter` stats never change
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equipment {
+    pub slot: Slot,
+    pub equipped: bool,
+    pub power_bonus: i32,
+    pub defense_bonus: i32,
+    pub max_hp_bonus: i32,
+}
+
+pub struct EquipmentKind {
+    pub name: &'static str,
+    pub glyph: char,
+    pub color: Color,
+    pub slot: Slot,
+    pub power_bonus: i32,
+    pub defense_bonus: i32,
+    pub max_hp_bonus: i32,
+}
+
+impl EquipmentKind {
+    pub fn equipment(&self) -> Equipment {
+        Equipment {
+            slot: self.slot,
+            equipped: false,
+            power_bonus: self.power_bonus,
+            defense_bonus: self.defense_bonus,
+            max_hp_bonus: self.max_hp_bonus,
+        }
+    }
+}
+
+// everything that can be found lying around, all equally likely
+pub const EQUIPMENT_KINDS: [EquipmentKind; 8] = [
+    EquipmentKind {
+        name: "dagger",
+        glyph: ')',
+        color: color::SKY,
+        slot: Slot::Weapon,
+        power_bonus: 2,
+        defense_bonus: 0,
+        max_hp_bonus: 0,
+    },
+    EquipmentKind {
+        name: "sword",
+        glyph: ')',
+        color: color::SKY,
+        slot: Slot::Weapon,
+        power_bonus: 3,
+        defense_bonus: 0,
+        max_hp_bonus: 0,
+    },
+    EquipmentKind {
+        name: "leather armor",
+        glyph: '[',
+        color: color::DARKER_ORANGE,
+        slot: Slot::Armor,
+        power_bonus: 0,
+        defense_bonus: 1,
+        max_hp_bonus: 0,
+    },
+    EquipmentKind {
+        name: "chain mail",
+        glyph: '[',
+        color: color::SKY,
+        slot: Slot::Armor,
+        power_bonus: 0,
+        defense_bonus: 2,
+        max_hp_bonus: 0,
+    },
+    EquipmentKind {
+        name: "wooden shield",
+        glyph: ']',
+        color: color::DARKER_ORANGE,
+        slot: Slot::Shield,
+        power_bonus: 0,
+        defense_bonus: 1,
+        max_hp_bonus: 0,
+    },
+    EquipmentKind {
+        name: "iron helmet",
+        glyph: '^',
+        color: color::SKY,
+        slot: Slot::Helmet,
+        power_bonus: 0,
+        defense_bonus: 1,
+        max_hp_bonus: 5,
+    },
+    EquipmentKind {
+        name: "ring of strength",
+        glyph: '=',
+        color: color::GOLD,
+        slot: Slot::Ring,
+        power_bonus: 1,
+        defense_bonus: 0,
+        max_hp_bonus: 0,
+    },
+    EquipmentKind {
+        name: "amulet of life",
+        glyph: '"',
+        color: color::GOLD,
+        slot: Slot::Amulet,
+        power_bonus: 0,
+        defense_bonus: 0,
+        max_hp_bonus: 10,
+    },
+];
@@ -1,12 +1,162 @@
-use tcod::map::{FovAlgorithm, Map as FovMap};
-
 use crate::tile::{Map, MAP_HEIGHT, MAP_WIDTH};
 
-pub const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
 pub const FOV_LIGHT_WALLS: bool = true;
 pub const TORCH_RADIUS: i32 = 10;
+// how far a blind player still sees, only the tiles right around it
+pub const BLIND_RADIUS: i32 = 1;
+
+// pure rust replacement for the tcod fov map, uses the same ray casting idea
+// as libtcod's FOV_BASIC so the game looks the same with or without tcod
+#[derive(Clone, Debug)]
+pub struct FovMap {
+    width: i32,
+    height: i32,
+    transparent: Vec<bool>,
+    walkable: Vec<bool>,
+    in_fov: Vec<bool>,
+}
+
+impl Default for FovMap {
+    fn default() -> Self {
+        FovMap::new(MAP_WIDTH, MAP_HEIGHT)
+    }
+}
+
+impl FovMap {
+    pub fn new(width: i32, height: i32) -> Self {
+        let size = (width * height) as usize;
+        FovMap {
+            width,
+            height,
+            transparent: vec![false; size],
+            walkable: vec![false; size],
+            in_fov: vec![false; size],
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            None
+        } else {
+            Some((y * self.width + x) as usize)
+        }
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, transparent: bool, walkable: bool) {
+        if let Some(index) = self.index(x, y) {
+            self.transparent[index] = transparent;
+            self.walkable[index] = walkable;
+        }
+    }
+
+    pub fn is_transparent(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some_and(|i| self.transparent[i])
+    }
+
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some_and(|i| self.walkable[i])
+    }
+
+    pub fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some_and(|i| self.in_fov[i])
+    }
+
+    fn light(&mut self, x: i32, y: i32) {
+        if let Some(index) = self.index(x, y) {
+            self.in_fov[index] = true;
+        }
+    }
+
+    pub fn compute_fov(&mut self, x: i32, y: i32, radius: i32, light_walls: bool) {
+        self.in_fov.iter_mut().for_each(|cell| *cell = false);
+        if self.index(x, y).is_none() {
+            return;
+        }
+        self.light(x, y);
+
+        // cast a ray to every cell on the border of the square around the origin
+        let (min_x, max_x) = (x - radius, x + radius);
+        let (min_y, max_y) = (y - radius, y + radius);
+        for target_x in min_x..=max_x {
+            self.cast_ray(x, y, target_x, min_y, radius, light_walls);
+            self.cast_ray(x, y, target_x, max_y, radius, light_walls);
+        }
+        for target_y in min_y + 1..max_y {
+            self.cast_ray(x, y, min_x, target_y, radius, light_walls);
+            self.cast_ray(x, y, max_x, target_y, radius, light_walls);
+        }
+
+        if light_walls {
+            self.light_walls_around_visible(x, y, radius);
+        }
+    }
+
+    fn cast_ray(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, radius: i32, light_walls: bool) {
+        // bresenham line from the origin to the target cell
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+
+        while (x, y) != (x1, y1) {
+            let doubled_error = 2 * error;
+            if doubled_error >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled_error <= dx {
+                error += dx;
+                y += step_y;
+            }
+
+            if self.index(x, y).is_none() || !in_radius(x - x0, y - y0, radius) {
+                return;
+            }
+            if self.is_transparent(x, y) {
+                self.light(x, y);
+            } else {
+                if light_walls {
+                    self.light(x, y);
+                }
+                return;
+            }
+        }
+    }
+
+    // rays often slip past wall corners, so walls right behind a visible floor
+    // cell (looking from the origin) are lit as well
+    fn light_walls_around_visible(&mut self, x0: i32, y0: i32, radius: i32) {
+        let mut to_light = vec![];
+        for y in y0 - radius..=y0 + radius {
+            for x in x0 - radius..=x0 + radius {
+                if !self.is_in_fov(x, y) || !self.is_transparent(x, y) {
+                    continue;
+                }
+                let dx = (x - x0).signum();
+                let dy = (y - y0).signum();
+                for (nx, ny) in [(x + dx, y), (x, y + dy), (x + dx, y + dy)] {
+                    if self.index(nx, ny).is_some()
+                        && !self.is_transparent(nx, ny)
+                        && in_radius(nx - x0, ny - y0, radius)
+                    {
+                        to_light.push((nx, ny));
+                    }
+                }
+            }
+        }
+        for (x, y) in to_light {
+            self.light(x, y);
+        }
+    }
+}
+
+fn in_radius(dx: i32, dy: i32, radius: i32) -> bool {
+    radius <= 0 || dx * dx + dy * dy <= radius * radius
+}
 
-pub fn generate_fov_map(fov: &mut FovMap, map: &mut Map) {
+pub fn generate_fov_map(fov: &mut FovMap, map: &Map) {
     for y in 0..MAP_HEIGHT {
         for x in 0..MAP_WIDTH {
             let transparent = !map[x as usize][y as usize].get_is_block_sight();
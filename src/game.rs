@@ -0,0 +1,414 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bsp::generate_bsp,
+    cave::generate_caves,
+    color::{RED, VIOLET, WHITE, YELLOW},
+    entity::{DeathCallback, Entity, Item, Stairs, UseResult, FREDERIC, PLAYER},
+    fov::{self, generate_fov_map, FovMap},
+    message::{Category, Messages},
+    morgue::History,
+    rng::{self, GameRng},
+    room::Room,
+    status::Effect,
+    tile::{Map, Tile, TileKind, MAP_HEIGHT, MAP_WIDTH},
+    validate::{repair_map, validate_map},
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct Game {
+    pub map: Map,
+    pub messages: Messages,
+    pub inventory: Vec<Entity>,
+    pub dungeon_level: u32,
+    pub seed: u64,
+    pub rng: GameRng,
+    // turns taken by the player, see `action::apply_action`
+    #[serde(default)]
+    pub turn: u32,
+    // kills and the cause of death, see `morgue`
+    #[serde(default)]
+    pub history: History,
+    // every visited level, `levels[dungeon_level - 1]` is empty while the
+    // player is on it since its map and entities are the ones in play
+    pub levels: Vec<Option<Level>>,
+    // generator of each dungeon level, see `MapGenerator::for_level`. Left out of
+    // the save while empty so older saves and replays keep their state hash
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub generators: Vec<MapGenerator>,
+    // rebuilt from the map after loading, see `generate_fov_map`
+    #[serde(skip)]
+    pub fov: FovMap,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Level {
+    pub map: Map,
+    // everything except the player and Frederic, they travel with the player
+    pub entities: Vec<Entity>,
+}
+
+// which algorithm lays out the rooms of a dungeon level
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MapGenerator {
+    // random rectangles, overlapping ones are thrown away
+    Rooms,
+    // binary space partition, one room per leaf
+    Bsp,
+    // cellular automata caves without any rooms
+    Caves,
+}
+
+impl MapGenerator {
+    // `generators[dungeon_level - 1]`, the last one is used for every deeper level
+    // and without any the generators take turns
+    pub fn for_level(dungeon_level: u32, generators: &[MapGenerator]) -> Self {
+        if let Some(&last) = generators.last() {
+            *generators.get(dungeon_level as usize - 1).unwrap_or(&last)
+        } else if dungeon_level.is_multiple_of(3) {
+            MapGenerator::Caves
+        } else if dungeon_level.is_multiple_of(2) {
+            MapGenerator::Bsp
+        } else {
+            MapGenerator::Rooms
+        }
+    }
+}
+
+impl Game {
+    pub fn compute_fov(&mut self, entities: &[Entity]) {
+        let (x, y) = entities[PLAYER].get_coordinates();
+        let radius = if entities[PLAYER].has_effect(Effect::Blind) {
+            fov::BLIND_RADIUS
+        } else {
+            fov::TORCH_RADIUS
+        };
+        self.fov.compute_fov(x, y, radius, fov::FOV_LIGHT_WALLS);
+
+        for y in 0..MAP_HEIGHT {
+            for x in 0..MAP_WIDTH {
+                if self.fov.is_in_fov(x, y) {
+                    self.map[x as usize][y as usize].explore();
+                }
+            }
+        }
+    }
+
+    // changes a single tile and keeps the fov map in sync with it,
+    // cheaper than rebuilding the whole fov map with `generate_fov_map`
+    pub fn set_tile_kind(&mut self, x: i32, y: i32, kind: TileKind) {
+        let tile = &mut self.map[x as usize][y as usize];
+        tile.set_kind(kind);
+        self.fov
+            .set(x, y, !tile.get_is_block_sight(), tile.get_is_passable());
+    }
+}
+
+// the player and Frederic, they are always the first two entities
+pub fn starting_entities() -> Vec<Entity> {
+    let mut player = Entity::new(0, 0, '@', WHITE, "Player", true);
+    player.make_alive();
+    player.make_fighter(30, 30, 2, 5, 0, DeathCallback::Player);
+    let npc = Entity::new(0, 0, '@', YELLOW, "Frederic", true);
+    vec![player, npc]
+}
+
+pub fn new_game(seed: u64) -> (Game, Vec<Entity>) {
+    new_game_with_generators(seed, vec![])
+}
+
+// the generators are kept in the game, every level of it is laid out by the same rules
+pub fn new_game_with_generators(seed: u64, generators: Vec<MapGenerator>) -> (Game, Vec<Entity>) {
+    let mut rng = rng::new_rng(seed);
+    let mut entities = starting_entities();
+
+    let generator = MapGenerator::for_level(1, &generators);
+    let map = make_map(&mut entities, generator, &mut rng);
+    let messages = Messages::new();
+    let inventory: Vec<Entity> = vec![];
+
+    let mut game: Game = Game {
+        map,
+        messages,
+        inventory,
+        dungeon_level: 1,
+        seed,
+        rng,
+        turn: 0,
+        history: History::default(),
+        levels: vec![None],
+        generators,
+        fov: FovMap::default(),
+    };
+    generate_fov_map(&mut game.fov, &game.map);
+    game.compute_fov(&entities);
+    game.messages.add(
+        "Welcome stranger! Prepre to perish in the Tombs of the Ancient Kings.",
+        RED,
+        Category::System,
+    );
+    (game, entities)
+}
+
+// every level returned from here is checked with `validate_map`, if the generator
+// left something unreachable the map is repaired or generated again
+pub fn make_map(entities: &mut Vec<Entity>, generator: MapGenerator, rng: &mut GameRng) -> Map {
+    let kept_entities = entities.len();
+    loop {
+        entities.truncate(kept_entities);
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+
+        match generator {
+            MapGenerator::Rooms => Room::generate_rooms(&mut map, entities, rng),
+            MapGenerator::Bsp => generate_bsp(&mut map, entities, rng),
+            MapGenerator::Caves => generate_caves(&mut map, entities, rng),
+        }
+
+        if validate_map(&map, entities).is_valid() || repair_map(&mut map, entities, rng).is_valid()
+        {
+            return map;
+        }
+    }
+}
+
+pub fn stairs_under_player(entities: &[Entity]) -> Option<Stairs> {
+    entities
+        .iter()
+        .filter(|e| e.get_coordinates() == entities[PLAYER].get_coordinates())
+        .find_map(|e| e.get_stairs())
+}
+
+// a tile the player can see and that is no further away than `max_range`
+pub fn can_target(x: i32, y: i32, max_range: i32, game: &Game, entities: &[Entity]) -> bool {
+    game.fov.is_in_fov(x, y) && entities[PLAYER].distance(x, y) <= max_range as f32
+}
+
+// the target is a tile picked by the player for items with a `target_range`
+pub fn use_item(
+    inventory_id: usize,
+    target: Option<(i32, i32)>,
+    game: &mut Game,
+    entities: &mut [Entity],
+) {
+    if let Some(item) = game.inventory[inventory_id].get_item() {
+        let on_use = match item {
+            Item::Heal => Entity::cast_heal,
+            Item::Lightning => Entity::cast_lightning,
+            Item::Key => Entity::use_key,
+            Item::Equipment => Entity::toggle_equipment,
+            Item::Confuse => Entity::cast_confuse,
+            Item::Fireball => Entity::cast_fireball,
+            Item::Teleport => Entity::cast_teleport,
+        };
+        match on_use(inventory_id, target, game, entities) {
+            UseResult::UsedUp => {
+                game.inventory.remove(inventory_id);
+            }
+            UseResult::UsedAndKept => {}
+            UseResult::Cancelled => {
+                game.messages.add("Cancelled", WHITE, Category::Item);
+            }
+        }
+    } else {
+        game.messages.add(
+            format!(
+                "The {} cannot be used.",
+                game.inventory[inventory_id].get_name()
+            ),
+            WHITE,
+            Category::Item,
+        )
+    }
+}
+
+fn leave_level(game: &mut Game, entities: &mut Vec<Entity>) {
+    let level = Level {
+        map: std::mem::take(&mut game.map),
+        entities: entities.split_off(FREDERIC + 1),
+    };
+    game.levels[game.dungeon_level as usize - 1] = Some(level);
+}
+
+// puts the stored level back in play with the player standing on its `arrive_on` stairs,
+// returns false if the level was never visited before
+fn enter_stored_level(game: &mut Game, entities: &mut Vec<Entity>, arrive_on: Stairs) -> bool {
+    let stored = game
+        .levels
+        .get_mut(game.dungeon_level as usize - 1)
+        .and_then(Option::take);
+    let Some(level) = stored else {
+        return false;
+    };
+    game.map = level.map;
+    entities.extend(level.entities);
+
+    let stairs_position = entities
+        .iter()
+        .find(|e| e.get_stairs() == Some(arrive_on))
+        .map(|e| e.get_coordinates());
+    if let Some((x, y)) = stairs_position {
+        entities[PLAYER].set_position(x, y);
+    }
+    // Frederic follows the player to the closest free tile
+    let (x, y) = entities[PLAYER].get_coordinates();
+    let free_tile = Tile::flood_fill(x, y, &game.map)
+        .into_iter()
+        .skip(1)
+        .find(|&(fx, fy)| !Tile::is_blocked(fx, fy, &game.map, entities));
+    if let Some((fx, fy)) = free_tile {
+        entities[FREDERIC].set_position(fx, fy);
+    }
+    true
+}
+
+pub fn next_level(game: &mut Game, entities: &mut Vec<Entity>) {
+    assert_eq!(&entities[PLAYER] as *const _, &entities[0] as *const _);
+    // everything after Player and Frederic stays on the level we leave
+    leave_level(game, entities);
+    game.dungeon_level += 1;
+
+    if enter_stored_level(game, entities, Stairs::Up) {
+        game.messages.add(
+            format!("You descend back to level {}.", game.dungeon_level),
+            VIOLET,
+            Category::Level,
+        );
+    } else {
+        game.messages.add(
+            "You take a moment to rest, and recover your strength.",
+            VIOLET,
+            Category::Level,
+        );
+        let heal_hp_amount = entities[PLAYER].max_hp(game) / 2;
+        entities[PLAYER].heal(heal_hp_amount, game);
+
+        game.messages.add(
+            "After a rare moment of peace you descend deeper into \
+        the heart of the dungeon...",
+            RED,
+            Category::Level,
+        );
+        game.levels.push(None);
+        let generator = MapGenerator::for_level(game.dungeon_level, &game.generators);
+        game.map = make_map(entities, generator, &mut game.rng);
+        // the way back up is where the player arrives
+        let (x, y) = entities[PLAYER].get_coordinates();
+        entities.push(Entity::new_stairs(x, y, Stairs::Up));
+    }
+    generate_fov_map(&mut game.fov, &game.map);
+    game.compute_fov(entities);
+}
+
+pub fn previous_level(game: &mut Game, entities: &mut Vec<Entity>) {
+    if game.dungeon_level <= 1 {
+        return;
+    }
+    // saves from before levels were kept only have the one the player is on
+    let above = game.levels.get(game.dungeon_level as usize - 2);
+    if !matches!(above, Some(Some(_))) {
+        game.messages.add(
+            "The way up has caved in, there is no going back.",
+            VIOLET,
+            Category::Level,
+        );
+        return;
+    }
+    leave_level(game, entities);
+    game.dungeon_level -= 1;
+    enter_stored_level(game, entities, Stairs::Down);
+    game.messages.add(
+        format!("You climb back up to level {}.", game.dungeon_level),
+        VIOLET,
+        Category::Level,
+    );
+    generate_fov_map(&mut game.fov, &game.map);
+    game.compute_fov(entities);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile_kinds(map: &Map) -> Vec<TileKind> {
+        map.iter().flatten().map(Tile::get_kind).collect()
+    }
+
+    #[test]
+    fn new_game_is_ready_to_play() {
+        let (game, entities) = new_game(42);
+        assert_eq!(game.seed, 42);
+        assert_eq!(game.dungeon_level, 1);
+        assert_eq!(game.turn, 0);
+        assert!(entities[PLAYER].is_alive());
+        assert_eq!(entities[FREDERIC].get_name(), "Frederic");
+        let (x, y) = entities[PLAYER].get_coordinates();
+        assert!(game.map[x as usize][y as usize].get_is_passable());
+        assert!(game.fov.is_in_fov(x, y));
+        assert!(entities
+            .iter()
+            .any(|e| e.get_stairs() == Some(Stairs::Down)));
+
+        let (same_game, _) = new_game(42);
+        assert_eq!(tile_kinds(&same_game.map), tile_kinds(&game.map));
+    }
+
+    #[test]
+    fn generators_follow_the_settings_or_take_turns() {
+        let default_rules: Vec<_> = (1..=6)
+            .map(|level| MapGenerator::for_level(level, &[]))
+            .collect();
+        assert_eq!(
+            default_rules,
+            [
+                MapGenerator::Rooms,
+                MapGenerator::Bsp,
+                MapGenerator::Caves,
+                MapGenerator::Bsp,
+                MapGenerator::Rooms,
+                MapGenerator::Caves,
+            ]
+        );
+
+        let generators = [MapGenerator::Caves, MapGenerator::Rooms];
+        assert_eq!(MapGenerator::for_level(1, &generators), MapGenerator::Caves);
+        assert_eq!(MapGenerator::for_level(2, &generators), MapGenerator::Rooms);
+        assert_eq!(MapGenerator::for_level(9, &generators), MapGenerator::Rooms);
+
+        // the first level is made of rooms by default, the same seed gives caves here
+        let (caves, _) = new_game_with_generators(5, vec![MapGenerator::Caves]);
+        let (rooms, _) = new_game(5);
+        assert_eq!(caves.generators, [MapGenerator::Caves]);
+        assert_ne!(tile_kinds(&caves.map), tile_kinds(&rooms.map));
+    }
+
+    #[test]
+    fn levels_are_kept_when_going_down_and_back_up() {
+        let (mut game, mut entities) = new_game(7);
+        let first_map = tile_kinds(&game.map);
+        let first_entities = entities.len();
+
+        next_level(&mut game, &mut entities);
+        assert_eq!(game.dungeon_level, 2);
+        assert_eq!(stairs_under_player(&entities), Some(Stairs::Up));
+        assert_ne!(tile_kinds(&game.map), first_map);
+
+        previous_level(&mut game, &mut entities);
+        assert_eq!(game.dungeon_level, 1);
+        assert_eq!(stairs_under_player(&entities), Some(Stairs::Down));
+        assert_eq!(tile_kinds(&game.map), first_map);
+        assert_eq!(entities.len(), first_entities);
+    }
+
+    #[test]
+    fn going_up_to_a_level_that_was_never_kept_does_nothing() {
+        let (mut game, mut entities) = new_game(8);
+        next_level(&mut game, &mut entities);
+        game.levels[0] = None;
+        let map = tile_kinds(&game.map);
+
+        previous_level(&mut game, &mut entities);
+        assert_eq!(game.dungeon_level, 2);
+        assert_eq!(tile_kinds(&game.map), map);
+        assert_eq!(stairs_under_player(&entities), Some(Stairs::Up));
+    }
+}
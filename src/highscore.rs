@@ -0,0 +1,203 @@
+use std::{error::Error, fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::{Entity, LEVEL_UP_BASE, LEVEL_UP_FACTOR, PLAYER},
+    game::Game,
+    save,
+};
+
+const HIGH_SCORES_FILE: &str = "highscores.json";
+pub const MAX_HIGH_SCORES: usize = 10;
+
+// points for every part of the score, going deeper is worth the most
+const POINTS_PER_DUNGEON_LEVEL: u32 = 500;
+const POINTS_PER_CHARACTER_LEVEL: u32 = 100;
+const POINTS_PER_KILL: u32 = 10;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HighScore {
+    pub score: u32,
+    pub seed: u64,
+    // finishes "You were ...", see `morgue::Death`
+    pub cause: String,
+    pub dungeon_level: u32,
+    pub character_level: i32,
+    pub xp: i32,
+    pub kills: u32,
+    pub turn: u32,
+    // see `save::now_secs`
+    pub date: u64,
+}
+
+impl HighScore {
+    pub fn new(game: &Game, entities: &[Entity]) -> Self {
+        let player = &entities[PLAYER];
+        let xp = total_xp(player);
+        let kills = game.history.total_kills();
+        HighScore {
+            score: score(game.dungeon_level, player.get_level(), xp, kills),
+            seed: game.seed,
+            cause: game
+                .history
+                .get_death()
+                .map_or("killed", |death| death.cause.as_str())
+                .to_string(),
+            dungeon_level: game.dungeon_level,
+            character_level: player.get_level(),
+            xp,
+            kills,
+            turn: game.turn,
+            date: save::now_secs(),
+        }
+    }
+}
+
+// the experience spent on level ups counts too, not only what is left of it
+fn total_xp(player: &Entity) -> i32 {
+    let spent: i32 = (1..player.get_level())
+        .map(|level| LEVEL_UP_BASE + level * LEVEL_UP_FACTOR)
+        .sum();
+    spent + player.get_fighter().map_or(0, |f| f.get_xp())
+}
+
+pub fn score(dungeon_level: u32, character_level: i32, xp: i32, kills: u32) -> u32 {
+    dungeon_level * POINTS_PER_DUNGEON_LEVEL
+        + character_level.max(0) as u32 * POINTS_PER_CHARACTER_LEVEL
+        + xp.max(0) as u32
+        + kills * POINTS_PER_KILL
+}
+
+// the best runs so far, highest score first
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    scores: Vec<HighScore>,
+}
+
+impl HighScores {
+    pub fn path() -> PathBuf {
+        save::data_dir().join(HIGH_SCORES_FILE)
+    }
+
+    // no file yet is an empty table
+    pub fn load() -> Result<HighScores, Box<dyn Error>> {
+        match fs::read_to_string(HighScores::path()) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HighScores::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let data = serde_json::to_string_pretty(self)?;
+        save::write_atomically(&HighScores::path(), data.as_bytes())?;
+        Ok(())
+    }
+
+    // the rank starting at 1 if the score made it into the table
+    pub fn add(&mut self, high_score: HighScore) -> Option<usize> {
+        // a tie goes below the older score
+        let index = self
+            .scores
+            .iter()
+            .position(|other| other.score < high_score.score)
+            .unwrap_or(self.scores.len());
+        if index >= MAX_HIGH_SCORES {
+            return None;
+        }
+        self.scores.insert(index, high_score);
+        self.scores.truncate(MAX_HIGH_SCORES);
+        Some(index + 1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HighScore> {
+        self.scores.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+}
+
+// adds the finished game to the high score file, the rank if it made it in
+pub fn record(game: &Game, entities: &[Entity]) -> Result<Option<usize>, Box<dyn Error>> {
+    let mut high_scores = HighScores::load()?;
+    let rank = high_scores.add(HighScore::new(game, entities));
+    if rank.is_some() {
+        high_scores.save()?;
+    }
+    Ok(rank)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `seed` tells the entries apart
+    fn high_score(score: u32, seed: u64) -> HighScore {
+        HighScore {
+            score,
+            seed,
+            cause: "killed".to_string(),
+            dungeon_level: 1,
+            character_level: 1,
+            xp: 0,
+            kills: 0,
+            turn: 0,
+            date: 0,
+        }
+    }
+
+    fn seeds(high_scores: &HighScores) -> Vec<u64> {
+        high_scores
+            .iter()
+            .map(|high_score| high_score.seed)
+            .collect()
+    }
+
+    #[test]
+    fn deeper_is_worth_the_most() {
+        assert_eq!(score(1, 1, 0, 0), 600);
+        assert_eq!(score(3, 2, 45, 4), 1500 + 200 + 45 + 40);
+        assert!(score(2, 1, 0, 0) > score(1, 5, 0, 0));
+        // negative levels or experience never take points away
+        assert_eq!(score(1, -1, -10, 0), 500);
+    }
+
+    #[test]
+    fn highest_score_ranks_first() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.add(high_score(100, 1)), Some(1));
+        assert_eq!(high_scores.add(high_score(300, 2)), Some(1));
+        assert_eq!(high_scores.add(high_score(200, 3)), Some(2));
+        assert_eq!(seeds(&high_scores), [2, 3, 1]);
+    }
+
+    #[test]
+    fn a_tie_goes_below_the_older_score() {
+        let mut high_scores = HighScores::default();
+        high_scores.add(high_score(200, 1));
+        high_scores.add(high_score(100, 2));
+        assert_eq!(high_scores.add(high_score(200, 3)), Some(2));
+        assert_eq!(seeds(&high_scores), [1, 3, 2]);
+    }
+
+    #[test]
+    fn the_table_keeps_only_the_best() {
+        let mut high_scores = HighScores::default();
+        for seed in 0..MAX_HIGH_SCORES as u64 {
+            high_scores.add(high_score(1000 - seed as u32 * 10, seed));
+        }
+        // as low as the last entry is a tie, so it does not make it in
+        let lowest = 1000 - (MAX_HIGH_SCORES as u32 - 1) * 10;
+        assert_eq!(high_scores.add(high_score(lowest, 100)), None);
+        assert_eq!(high_scores.iter().count(), MAX_HIGH_SCORES);
+
+        // a better one pushes the last entry out
+        assert_eq!(high_scores.add(high_score(995, 101)), Some(2));
+        assert_eq!(high_scores.iter().count(), MAX_HIGH_SCORES);
+        assert!(!seeds(&high_scores).contains(&(MAX_HIGH_SCORES as u64 - 1)));
+        assert_eq!(seeds(&high_scores)[1], 101);
+    }
+}
@@ -0,0 +1,429 @@
+use std::{collections::BTreeMap, fmt, io, path::PathBuf};
+
+use bug_free_fiesta::save;
+use tcod::input::{Key, KeyCode};
+
+const KEYBINDINGS_FILE: &str = "keybindings.json";
+
+// everything the player can do with a key press
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveUpLeft,
+    MoveUpRight,
+    MoveDownLeft,
+    MoveDownRight,
+    Wait,
+    PickUp,
+    Inventory,
+    Drop,
+    Character,
+    MessageLog,
+    Descend,
+    Ascend,
+    // goes down or up depending on the stairs the player stands on
+    UseStairs,
+    ShowBindings,
+    Exit,
+    Fullscreen,
+}
+
+impl Command {
+    pub const ALL: [Command; 20] = [
+        Command::MoveUp,
+        Command::MoveDown,
+        Command::MoveLeft,
+        Command::MoveRight,
+        Command::MoveUpLeft,
+        Command::MoveUpRight,
+        Command::MoveDownLeft,
+        Command::MoveDownRight,
+        Command::Wait,
+        Command::PickUp,
+        Command::Inventory,
+        Command::Drop,
+        Command::Character,
+        Command::MessageLog,
+        Command::Descend,
+        Command::Ascend,
+        Command::UseStairs,
+        Command::ShowBindings,
+        Command::Exit,
+        Command::Fullscreen,
+    ];
+
+    // the name used in the keybindings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::MoveUp => "move_up",
+            Command::MoveDown => "move_down",
+            Command::MoveLeft => "move_left",
+            Command::MoveRight => "move_right",
+            Command::MoveUpLeft => "move_up_left",
+            Command::MoveUpRight => "move_up_right",
+            Command::MoveDownLeft => "move_down_left",
+            Command::MoveDownRight => "move_down_right",
+            Command::Wait => "wait",
+            Command::PickUp => "pick_up",
+            Command::Inventory => "inventory",
+            Command::Drop => "drop",
+            Command::Character => "character",
+            Command::MessageLog => "message_log",
+            Command::Descend => "descend",
+            Command::Ascend => "ascend",
+            Command::UseStairs => "use_stairs",
+            Command::ShowBindings => "show_bindings",
+            Command::Exit => "exit",
+            Command::Fullscreen => "fullscreen",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Command::MoveUp => "Move up",
+            Command::MoveDown => "Move down",
+            Command::MoveLeft => "Move left",
+            Command::MoveRight => "Move right",
+            Command::MoveUpLeft => "Move up-left",
+            Command::MoveUpRight => "Move up-right",
+            Command::MoveDownLeft => "Move down-left",
+            Command::MoveDownRight => "Move down-right",
+            Command::Wait => "Wait a turn",
+            Command::PickUp => "Pick up",
+            Command::Inventory => "Use an item",
+            Command::Drop => "Drop an item",
+            Command::Character => "Character screen",
+            Command::MessageLog => "Message log",
+            Command::Descend => "Go down the stairs",
+            Command::Ascend => "Go up the stairs",
+            Command::UseStairs => "Use the stairs",
+            Command::ShowBindings => "Key bindings",
+            Command::Exit => "Save and exit",
+            Command::Fullscreen => "Toggle fullscreen",
+        }
+    }
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Command::MoveUp => &["Up", "NumPad8", "k"],
+            Command::MoveDown => &["Down", "NumPad2", "j"],
+            Command::MoveLeft => &["Left", "NumPad4", "h"],
+            Command::MoveRight => &["Right", "NumPad6", "l"],
+            Command::MoveUpLeft => &["NumPad7", "y"],
+            Command::MoveUpRight => &["NumPad9", "u"],
+            Command::MoveDownLeft => &["NumPad1", "b"],
+            Command::MoveDownRight => &["NumPad3", "n"],
+            Command::Wait => &["NumPad5", "."],
+            Command::PickUp => &["g"],
+            Command::Inventory => &["i"],
+            Command::Drop => &["d"],
+            Command::Character => &["c"],
+            Command::MessageLog => &["m"],
+            Command::Descend => &[">"],
+            Command::Ascend => &["<"],
+            Command::UseStairs => &["e"],
+            Command::ShowBindings => &["?"],
+            Command::Exit => &["Escape"],
+            Command::Fullscreen => &["Alt+Enter"],
+        }
+    }
+
+    // the step a movement command takes
+    pub fn direction(self) -> Option<(i32, i32)> {
+        match self {
+            Command::MoveUp => Some((0, -1)),
+            Command::MoveDown => Some((0, 1)),
+            Command::MoveLeft => Some((-1, 0)),
+            Command::MoveRight => Some((1, 0)),
+            Command::MoveUpLeft => Some((-1, -1)),
+            Command::MoveUpRight => Some((1, -1)),
+            Command::MoveDownLeft => Some((-1, 1)),
+            Command::MoveDownRight => Some((1, 1)),
+            _ => None,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Command> {
+        Command::ALL
+            .into_iter()
+            .find(|command| command.name() == name)
+    }
+}
+
+// keys that do not type a character, everything else is written as the
+// character itself, e.g. "g" or ">"
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Insert", KeyCode::Insert),
+    ("Delete", KeyCode::Delete),
+    ("Escape", KeyCode::Escape),
+    ("Enter", KeyCode::Enter),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Space", KeyCode::Spacebar),
+    ("NumPad0", KeyCode::NumPad0),
+    ("NumPad1", KeyCode::NumPad1),
+    ("NumPad2", KeyCode::NumPad2),
+    ("NumPad3", KeyCode::NumPad3),
+    ("NumPad4", KeyCode::NumPad4),
+    ("NumPad5", KeyCode::NumPad5),
+    ("NumPad6", KeyCode::NumPad6),
+    ("NumPad7", KeyCode::NumPad7),
+    ("NumPad8", KeyCode::NumPad8),
+    ("NumPad9", KeyCode::NumPad9),
+    ("NumPadEnter", KeyCode::NumPadEnter),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum KeyName {
+    Code(KeyCode),
+    Text(String),
+}
+
+// a key as written in the keybindings file, optionally with "Alt+" and "Ctrl+" in front
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeySpec {
+    key: KeyName,
+    alt: bool,
+    ctrl: bool,
+    written: String,
+}
+
+impl KeySpec {
+    fn parse(written: &str) -> Option<KeySpec> {
+        let mut rest = written;
+        let (mut alt, mut ctrl) = (false, false);
+        loop {
+            if let Some(after) = rest.strip_prefix("Alt+") {
+                alt = true;
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("Ctrl+") {
+                ctrl = true;
+                rest = after;
+            } else {
+                break;
+            }
+        }
+
+        let key = if rest.chars().count() == 1 {
+            KeyName::Text(rest.to_string())
+        } else {
+            let &(_, code) = NAMED_KEYS.iter().find(|(name, _)| *name == rest)?;
+            KeyName::Code(code)
+        };
+        Some(KeySpec {
+            key,
+            alt,
+            ctrl,
+            written: written.to_string(),
+        })
+    }
+
+    fn matches(&self, key: Key) -> bool {
+        if self.alt != key.alt || self.ctrl != key.ctrl {
+            return false;
+        }
+        match &self.key {
+            KeyName::Code(code) => key.code == *code,
+            KeyName::Text(text) => key.code == KeyCode::Text && key.text() == text,
+        }
+    }
+
+    fn is_same_key(&self, other: &KeySpec) -> bool {
+        self.key == other.key && self.alt == other.alt && self.ctrl == other.ctrl
+    }
+}
+
+impl fmt::Display for KeySpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.written)
+    }
+}
+
+#[derive(Debug)]
+pub enum BindingError {
+    Read(io::Error),
+    Parse(serde_json::Error),
+    UnknownCommand(String),
+    UnknownKey {
+        command: Command,
+        key: String,
+    },
+    Conflict {
+        key: String,
+        first: Command,
+        second: Command,
+    },
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindingError::Read(e) => write!(f, "can not read {}: {}", KEYBINDINGS_FILE, e),
+            BindingError::Parse(e) => write!(f, "{} is not valid: {}", KEYBINDINGS_FILE, e),
+            BindingError::UnknownCommand(name) => write!(f, "unknown command \"{}\"", name),
+            BindingError::UnknownKey { command, key } => {
+                write!(f, "unknown key \"{}\" for {}", key, command.name())
+            }
+            BindingError::Conflict { key, first, second } => write!(
+                f,
+                "\"{}\" is already bound to {}, {} does without it",
+                key,
+                first.name(),
+                second.name()
+            ),
+        }
+    }
+}
+
+pub struct KeyBindings {
+    bindings: Vec<(Command, Vec<KeySpec>)>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let bindings = Command::ALL
+            .into_iter()
+            .map(|command| {
+                let keys = command
+                    .default_keys()
+                    .iter()
+                    .filter_map(|key| KeySpec::parse(key))
+                    .collect();
+                (command, keys)
+            })
+            .collect();
+        KeyBindings { bindings }
+    }
+}
+
+impl KeyBindings {
+    pub fn path() -> PathBuf {
+        save::data_dir().join(KEYBINDINGS_FILE)
+    }
+
+    // reads the keybindings file, commands missing from it keep their default keys.
+    // a missing file is fine, a broken entry is left out and returned so the player
+    // can be told about it, only a file that can not be read at all gives the defaults
+    pub fn load() -> (KeyBindings, Vec<BindingError>) {
+        match std::fs::read_to_string(KeyBindings::path()) {
+            Ok(text) => KeyBindings::from_json(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (KeyBindings::default(), vec![]),
+            Err(e) => (KeyBindings::default(), vec![BindingError::Read(e)]),
+        }
+    }
+
+    // the file maps command names to lists of keys, e.g. { "pick_up": ["g", "Alt+p"] }.
+    // a command whose keys are all unknown keeps its default ones and a key bound to
+    // two commands stays with the one listed first in `Command::ALL`
+    fn from_json(text: &str) -> (KeyBindings, Vec<BindingError>) {
+        let mut bindings = KeyBindings::default();
+        let file: BTreeMap<String, Vec<String>> = match serde_json::from_str(text) {
+            Ok(file) => file,
+            Err(e) => return (bindings, vec![BindingError::Parse(e)]),
+        };
+
+        let mut errors = vec![];
+        for (name, keys) in file {
+            let Some(command) = Command::from_name(&name) else {
+                errors.push(BindingError::UnknownCommand(name));
+                continue;
+            };
+            let is_unbound = keys.is_empty();
+            let mut specs = vec![];
+            for key in keys {
+                match KeySpec::parse(&key) {
+                    Some(spec) => specs.push(spec),
+                    None => errors.push(BindingError::UnknownKey { command, key }),
+                }
+            }
+            if specs.is_empty() && !is_unbound {
+                continue;
+            }
+            if let Some((_, bound)) = bindings.bindings.iter_mut().find(|(c, _)| *c == command) {
+                *bound = specs;
+            }
+        }
+
+        for i in 0..bindings.bindings.len() {
+            let (first, first_keys) = bindings.bindings[i].clone();
+            for (second, second_keys) in bindings.bindings[i + 1..].iter_mut() {
+                second_keys.retain(|key| {
+                    let is_conflict = first_keys.iter().any(|other| key.is_same_key(other));
+                    if is_conflict {
+                        errors.push(BindingError::Conflict {
+                            key: key.to_string(),
+                            first,
+                            second: *second,
+                        });
+                    }
+                    !is_conflict
+                });
+            }
+        }
+
+        (bindings, errors)
+    }
+
+    pub fn command(&self, key: Key) -> Option<Command> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.iter().any(|spec| spec.matches(key)))
+            .map(|(command, _)| *command)
+    }
+
+    // one line per command for the bindings screen
+    pub fn describe(&self) -> String {
+        let mut text = format!("Key bindings, edit {} to change them\n\n", KEYBINDINGS_FILE);
+        for (command, keys) in &self.bindings {
+            let keys = keys
+                .iter()
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            text.push_str(&format!("{:<20}{}\n", command.description(), keys));
+        }
+        text
+    }
+}
+
+pub fn describe_errors(errors: &[BindingError]) -> String {
+    let is_unusable = errors
+        .iter()
+        .any(|e| matches!(e, BindingError::Read(_) | BindingError::Parse(_)));
+    let outcome = if is_unusable {
+        "the default keys are used instead"
+    } else {
+        "these entries are left out"
+    };
+    let mut text = format!(
+        "\nThere are problems with {}, {}.\n\n",
+        KEYBINDINGS_FILE, outcome
+    );
+    for error in errors {
+        text.push_str(&format!("- {}\n", error));
+    }
+    text
+}
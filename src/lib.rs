@@ -0,0 +1,22 @@
+// game rules live here and only depend on pure rust code, the tcod front-end
+// in main.rs just draws the state and turns key presses into calls to this crate
+pub mod action;
+pub mod bsp;
+pub mod cave;
+pub mod color;
+pub mod entity;
+pub mod equipment;
+pub mod fov;
+pub mod game;
+pub mod highscore;
+pub mod message;
+pub mod morgue;
+pub mod pathfinding;
+pub mod replay;
+pub mod rng;
+pub mod room;
+pub mod save;
+pub mod settings;
+pub mod status;
+pub mod tile;
+pub mod validate;
@@ -0,0 +1,246 @@
+use bug_free_fiesta::message::{Category, Messages};
+use tcod::colors::{Color, BLACK, DARKER_GREY, DARK_BLUE, LIGHT_GREY, LIGHT_YELLOW, WHITE};
+use tcod::console::{Console, Root};
+use tcod::input::KeyCode::*;
+use tcod::{BackgroundFlag, TextAlignment};
+
+use crate::{input_box, to_tcod_color, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const LOG_X: i32 = 1;
+const LOG_WIDTH: i32 = SCREEN_WIDTH - 2;
+// the title above and the help line below take one row each
+const LOG_Y: i32 = 1;
+const LOG_HEIGHT: i32 = SCREEN_HEIGHT - 2;
+// the turn a message happened on goes in front of it
+const TURN_WIDTH: usize = 6;
+const SEARCH_WIDTH: i32 = 40;
+
+// one row of the log, long messages take several
+struct LogLine {
+    text: String,
+    color: Color,
+    message: usize,
+    // only the first row of a message counts when jumping between matches
+    is_first: bool,
+}
+
+// every message still in the log, newest at the bottom. Up/Down and PageUp/PageDown
+// scroll, '/' searches, 'n' and 'N' jump to the older or newer match, 1-4 show or
+// hide a category of messages and Escape closes. Hidden categories stay hidden
+// the next time the log is opened
+pub fn show_message_log(root: &mut Root, messages: &Messages, hidden: &mut Vec<Category>) {
+    let texts: Vec<String> = messages
+        .iter()
+        .map(|message| message.get_text().to_lowercase())
+        .collect();
+    let page = LOG_HEIGHT as usize;
+    let mut lines = log_lines(messages, hidden);
+    let mut top = lines.len().saturating_sub(page);
+    let mut query = String::new();
+    let mut current_match: Option<usize> = None;
+    let mut status = String::new();
+
+    while !root.window_closed() {
+        let last_top = lines.len().saturating_sub(page);
+        root.set_default_background(BLACK);
+        root.clear();
+        root.set_default_foreground(LIGHT_YELLOW);
+        root.print_ex(
+            LOG_X,
+            0,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            "MESSAGE LOG",
+        );
+        render_filters(root, hidden);
+        for (row, line) in lines.iter().skip(top).take(page).enumerate() {
+            let y = LOG_Y + row as i32;
+            if is_match(&texts, &query, line) {
+                let background = if current_match.is_some_and(|m| lines[m].message == line.message)
+                {
+                    DARK_BLUE
+                } else {
+                    DARKER_GREY
+                };
+                root.set_default_background(background);
+                root.rect(LOG_X, y, LOG_WIDTH, 1, false, BackgroundFlag::Set);
+            }
+            root.set_default_foreground(line.color);
+            root.print_ex(
+                LOG_X,
+                y,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                &line.text,
+            );
+        }
+        root.set_default_foreground(LIGHT_GREY);
+        let help = if status.is_empty() {
+            "PageUp/PageDown scroll  / search  n/N older/newer match  Escape closes"
+        } else {
+            &status
+        };
+        root.print_ex(
+            LOG_X,
+            SCREEN_HEIGHT - 1,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            help,
+        );
+        root.print_ex(
+            SCREEN_WIDTH - 1,
+            SCREEN_HEIGHT - 1,
+            BackgroundFlag::None,
+            TextAlignment::Right,
+            format!(
+                "{}-{}/{}",
+                (top + 1).min(lines.len()),
+                (top + page).min(lines.len()),
+                lines.len()
+            ),
+        );
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        status.clear();
+        let mut search_older = None;
+        match (key.code, key.printable) {
+            (Escape, _) => break,
+            (Up, _) => top = top.saturating_sub(1),
+            (Down, _) => top = (top + 1).min(last_top),
+            (PageUp, _) => top = top.saturating_sub(page),
+            (PageDown, _) => top = (top + page).min(last_top),
+            (Home, _) => top = 0,
+            (End, _) => top = last_top,
+            (_, '/') => {
+                if let Some(text) = input_box("Search the log for:\n", SEARCH_WIDTH, root) {
+                    query = text.to_lowercase();
+                    current_match = None;
+                    search_older = Some(true);
+                }
+            }
+            (_, 'n') => search_older = Some(true),
+            (_, 'N') => search_older = Some(false),
+            (_, '1'..='4') => {
+                let category = Category::ALL[key.printable as usize - '1' as usize];
+                match hidden.iter().position(|&hidden| hidden == category) {
+                    Some(index) => {
+                        hidden.remove(index);
+                    }
+                    None => hidden.push(category),
+                }
+                // the rows move around, start again from the newest message
+                lines = log_lines(messages, hidden);
+                top = lines.len().saturating_sub(page);
+                current_match = None;
+            }
+            _ => {}
+        }
+
+        let last_top = lines.len().saturating_sub(page);
+        if let (Some(older), false) = (search_older, query.is_empty()) {
+            let is_start_of_match =
+                |&i: &usize| lines[i].is_first && is_match(&texts, &query, &lines[i]);
+            let found = if older {
+                let start = current_match.unwrap_or(lines.len());
+                (0..start).rev().find(is_start_of_match)
+            } else {
+                let start = current_match.map_or(lines.len(), |m| m + 1);
+                (start..lines.len()).find(is_start_of_match)
+            };
+            match found {
+                Some(line) => {
+                    current_match = Some(line);
+                    if line < top || line >= top + page {
+                        top = line.saturating_sub(page / 2).min(last_top);
+                    }
+                }
+                None => status = format!("No more messages with \"{}\"", query),
+            }
+        }
+    }
+}
+
+// "1 combat  2 item ..." in the top right corner, hidden categories are greyed out
+fn render_filters(root: &mut Root, hidden: &[Category]) {
+    let labels: Vec<String> = Category::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, category)| format!("{} {}", index + 1, category.name()))
+        .collect();
+    let width: usize = labels.iter().map(|label| label.len() + 2).sum();
+    let mut x = SCREEN_WIDTH + 1 - width as i32;
+    for (label, category) in labels.iter().zip(Category::ALL) {
+        let color = if hidden.contains(&category) {
+            DARKER_GREY
+        } else {
+            WHITE
+        };
+        root.set_default_foreground(color);
+        root.print_ex(x, 0, BackgroundFlag::None, TextAlignment::Left, label);
+        x += label.len() as i32 + 2;
+    }
+}
+
+fn is_match(texts: &[String], query: &str, line: &LogLine) -> bool {
+    !query.is_empty() && texts[line.message].contains(query)
+}
+
+fn log_lines(messages: &Messages, hidden: &[Category]) -> Vec<LogLine> {
+    let mut lines = vec![];
+    let shown = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| !hidden.contains(&message.get_category()));
+    for (index, message) in shown {
+        let rows = wrap(&message.get_text(), LOG_WIDTH as usize - TURN_WIDTH);
+        for (row, text) in rows.into_iter().enumerate() {
+            let turn = if row == 0 {
+                message.get_turn().to_string()
+            } else {
+                String::new()
+            };
+            lines.push(LogLine {
+                text: format!("{:>width$} {}", turn, text, width = TURN_WIDTH - 1),
+                color: to_tcod_color(message.get_color()),
+                message: index,
+                is_first: row == 0,
+            });
+        }
+    }
+    lines
+}
+
+// breaks between words, a word longer than the width is cut
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word;
+        while word.chars().count() > width {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            let cut = word
+                .char_indices()
+                .nth(width)
+                .map_or(word.len(), |(i, _)| i);
+            lines.push(word[..cut].to_string());
+            word = &word[cut..];
+        }
+        if word.is_empty() {
+            continue;
+        }
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
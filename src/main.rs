@@ -1,43 +1,40 @@
-mod entity;
-mod fov;
-mod message;
+mod keybindings;
+mod log_viewer;
 mod panel;
-mod room;
-mod tile;
 
-use std::io::{Read, Write};
+use std::path::Path;
 
-use entity::{DeathCallback, Entity, Item, UseResult, LEVEL_UP_BASE, LEVEL_UP_FACTOR, PLAYER};
-use fov::generate_fov_map;
-use message::{Messages, MSG_HEIGHT, MSG_WIDTH, MSG_X};
+use bug_free_fiesta::action::{self, Action};
+use bug_free_fiesta::color;
+use bug_free_fiesta::entity::{Entity, Item, Stairs, Stat, PLAYER};
+use bug_free_fiesta::fov::FovMap;
+use bug_free_fiesta::game::{self, Game};
+use bug_free_fiesta::highscore::{self, HighScores};
+use bug_free_fiesta::message::Category;
+use bug_free_fiesta::morgue;
+use bug_free_fiesta::replay::{self, Playback, Replay};
+use bug_free_fiesta::rng;
+use bug_free_fiesta::save::{self, load_game, save_game, SaveError, SaveSlot};
+use bug_free_fiesta::settings::Settings;
+use bug_free_fiesta::tile::{MAP_HEIGHT, MAP_WIDTH};
+use keybindings::{Command, KeyBindings};
 use panel::render_bar;
-use room::Room;
-use serde::{Deserialize, Serialize};
-use tcod::colors::{
-    Color, BLACK, DARKER_RED, LIGHT_GREY, LIGHT_RED, LIGHT_YELLOW, RED, VIOLET, WHITE, YELLOW,
-};
+use tcod::colors::{Color, BLACK, DARKER_RED, LIGHT_GREY, LIGHT_RED, LIGHT_YELLOW, WHITE};
 use tcod::console::{blit, BackgroundFlag, Console, FontLayout, FontType, Offscreen, Root};
 use tcod::input::{self, KeyCode::*};
 use tcod::input::{Event, Key, Mouse};
-use tcod::map::Map as FovMap;
 use tcod::TextAlignment;
-use tile::{Map, Tile, MAP_HEIGHT, MAP_WIDTH};
 
 pub struct Tcod {
     root: Root,
     con: Offscreen,
     panel: Offscreen,
-    fov: FovMap,
     key: Key,
     mouse: Mouse,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct Game {
-    map: Map,
-    messages: Messages,
-    inventory: Vec<Entity>,
-    dungeon_level: u32,
+    bindings: KeyBindings,
+    settings: Settings,
+    // message categories the message log does not show
+    hidden_categories: Vec<Category>,
 }
 
 const FONT_SIZE: i32 = 10;
@@ -45,29 +42,25 @@ const SCREEN_WIDTH: i32 = 8 * FONT_SIZE;
 const SCREEN_HEIGHT: i32 = 5 * FONT_SIZE;
 const LEVEL_SCREEN_WIDTH: i32 = 40;
 const CHARACTER_SCREEN_WIDTH: i32 = 30;
+const SEED_SCREEN_WIDTH: i32 = 30;
+const LOAD_ERROR_WIDTH: i32 = 40;
+const SAVE_MENU_WIDTH: i32 = 60;
+const KEYBINDINGS_SCREEN_WIDTH: i32 = 50;
+const DEATH_SCREEN_WIDTH: i32 = 60;
+const HIGH_SCORES_WIDTH: i32 = 70;
 
 pub const BAR_WIDTH: i32 = 20;
 pub const PANEL_HEIGHT: i32 = 7;
 pub const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
 
-const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
-const COLOR_DARK_GROUND: Color = Color {
-    r: 50,
-    g: 50,
-    b: 150,
-};
-const COLOR_LIGHT_WALL: Color = Color {
-    r: 130,
-    g: 110,
-    b: 50,
-};
-const COLOR_LIGHT_GROUND: Color = Color {
-    r: 200,
-    g: 180,
-    b: 50,
-};
+const MSG_X: i32 = BAR_WIDTH + 2;
+const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
+const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 
 const LIMIT_FPS: i32 = 24;
+// frames between two actions while watching a replay, from slowest to fastest
+const REPLAY_SPEEDS: [i32; 5] = [24, 12, 6, 3, 1];
+const DEFAULT_REPLAY_SPEED: usize = 2;
 const INVENTORY_WIDTH: i32 = 50;
 
 fn main() {
@@ -82,56 +75,84 @@ fn main() {
 
     let con = Offscreen::new(MAP_WIDTH, MAP_HEIGHT);
     let panel = Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT);
-    let fov = FovMap::new(MAP_WIDTH, MAP_HEIGHT);
     let key = Default::default();
     let mouse = Default::default();
+    let (bindings, binding_errors) = KeyBindings::load();
+    let (settings, settings_error) = Settings::load();
 
     let mut tcod = Tcod {
         root,
         con,
         panel,
-        fov,
         key,
         mouse,
+        bindings,
+        settings,
+        hidden_categories: vec![],
     };
 
-    main_menu(&mut tcod);
+    if !binding_errors.is_empty() {
+        let text = keybindings::describe_errors(&binding_errors);
+        msgbox(&text, KEYBINDINGS_SCREEN_WIDTH, &mut tcod.root);
+    }
+    if let Some(e) = settings_error {
+        let text = format!("\n{}\n\nUsing the default settings.\n", e);
+        msgbox(&text, KEYBINDINGS_SCREEN_WIDTH, &mut tcod.root);
+    }
+    match save::import_legacy_save() {
+        Ok(Some(slot)) => {
+            let text = format!(
+                "\nThe old savegame.json was moved to the slot \"{}\".\n",
+                slot
+            );
+            msgbox(&text, LOAD_ERROR_WIDTH, &mut tcod.root);
+        }
+        Ok(None) => {}
+        Err(e) => {
+            let text = format!("\nThe old savegame.json can not be imported:\n{}\n", e);
+            msgbox(&text, LOAD_ERROR_WIDTH, &mut tcod.root);
+        }
+    }
+    if let Some(path) = replay::replay_from_args() {
+        match Replay::load(Path::new(&path)) {
+            Ok(replay) => play_replay(&mut tcod, replay),
+            Err(e) => {
+                let text = format!("\nCan not load the replay {}:\n{}\n", path, e);
+                msgbox(&text, INVENTORY_WIDTH, &mut tcod.root);
+            }
+        }
+        return;
+    }
+    main_menu(&mut tcod, rng::seed_from_args());
 }
 
-fn new_game(tcod: &mut Tcod) -> (Game, Vec<Entity>) {
-    let mut player = entity::Entity::new(0, 0, '@', WHITE, "Player", true);
-    player.make_alive();
-    player.make_fighter(30, 30, 2, 5, 0, DeathCallback::Player);
-    let npc = entity::Entity::new(
-        SCREEN_WIDTH / 2 - 5,
-        SCREEN_HEIGHT / 2,
-        '@',
-        YELLOW,
-        "Frederic",
-        true,
-    );
-    let mut entities = vec![player, npc];
-
-    let mut map = make_map(&mut entities);
-    generate_fov_map(&mut tcod.fov, &mut map);
-    let messages = Messages::new();
-    let inventory: Vec<Entity> = vec![];
-
-    let mut game: Game = Game {
-        map,
-        messages,
-        inventory,
-        dungeon_level: 1,
-    };
-    game.messages.add(
-        "Welcome stranger! Prepre to perish in the Tombs of the Ancient Kings.",
-        RED,
-    );
-    (game, entities)
+// the game core uses its own color type so it can run without tcod
+fn to_tcod_color(color: color::Color) -> Color {
+    Color::new(color.r, color.g, color.b)
 }
 
-fn play_game(tcod: &mut Tcod, game: &mut Game, entities: &mut Vec<Entity>) {
-    let mut previous_player_position = (-1, -1);
+// every applied action goes into `replay`, it is written next to the save every time the game
+// is saved: on every level change, every few turns, when the window is closed and on exit
+fn play_game(
+    tcod: &mut Tcod,
+    slot: &str,
+    game: &mut Game,
+    entities: &mut Vec<Entity>,
+    mut replay: Option<Replay>,
+) {
+    game.messages.set_capacity(tcod.settings.message_capacity);
+    if tcod.settings.log_old_messages {
+        let path = save::message_log_path(slot);
+        if let Err(e) = game.messages.set_overflow_log(path) {
+            game.messages.add(
+                format!("Older messages can not be kept: {}", e),
+                color::RED,
+                Category::System,
+            );
+        }
+    }
+    let mut last_autosave = (game.turn, game.dungeon_level);
+
     // main game loop
     while !tcod.root.window_closed() {
         // prepare and draw scene
@@ -144,28 +165,205 @@ fn play_game(tcod: &mut Tcod, game: &mut Game, entities: &mut Vec<Entity>) {
             _ => tcod.key = Default::default(),
         }
 
-        render_all(tcod, game, &entities, previous_player_position);
+        render_all(tcod, game, entities);
 
         // draw everything
         tcod.root.flush();
 
-        Entity::level_up(tcod, game, entities);
+        let level_up_action = level_up(tcod, entities).map(Action::LevelUp);
         // let key = tcod.root.wait_for_keypress(true);
 
         // game controls
-        previous_player_position = entities[PLAYER].get_coordinates();
-        let took_turn = player_controls(tcod.key, game, entities, tcod);
-        let is_exit_presed = system_controls(tcod.key, &mut tcod.root);
-        Entity::mobs_turn(game, &tcod.fov, entities, took_turn);
+        let action = player_controls(tcod.key, game, entities, tcod);
+        let is_exit_presed = system_controls(tcod.key, &tcod.bindings, &mut tcod.root);
+        for action in level_up_action.into_iter().chain(action) {
+            if let Some(replay) = replay.as_mut() {
+                replay.record(action);
+            }
+            action::apply_action(game, entities, action);
+        }
+
+        if !entities[PLAYER].is_alive() {
+            // the last save stays as it was, but the death is recorded
+            if let Some(replay) = replay.as_mut() {
+                if let Err(e) = write_replay(replay, slot, game, entities) {
+                    msgbox(&format!("\n{}\n", e), LOAD_ERROR_WIDTH, &mut tcod.root);
+                }
+            }
+            render_all(tcod, game, entities);
+            tcod.root.flush();
+            death_screen(tcod, slot, game, entities);
+            return;
+        }
+        let is_autosave_due = game.dungeon_level != last_autosave.1
+            || tcod.settings.is_autosave_due(last_autosave.0, game.turn);
+        if is_autosave_due {
+            autosave(&tcod.settings, slot, game, entities, replay.as_mut());
+            last_autosave = (game.turn, game.dungeon_level);
+        }
+
         if is_exit_presed {
-            save_game(game, entities).unwrap();
+            // keep playing if the game could not be saved, leaving would lose it
+            if let Err(e) = save_game(slot, tcod.settings.save_format, game, entities) {
+                let text = format!("\nThe game could not be saved.\n\n{}\n", e);
+                msgbox(&text, LOAD_ERROR_WIDTH, &mut tcod.root);
+                continue;
+            }
+            if let Some(replay) = replay.as_mut() {
+                if let Err(e) = write_replay(replay, slot, game, entities) {
+                    msgbox(&format!("\n{}\n", e), LOAD_ERROR_WIDTH, &mut tcod.root);
+                }
+            }
             break;
         }
         // end of the main loop
     }
+    if tcod.root.window_closed() {
+        autosave(&tcod.settings, slot, game, entities, replay.as_mut());
+    }
+}
+
+// sums up the game and writes it to the morgue, with permadeath the save goes away too
+fn death_screen(tcod: &mut Tcod, slot: &str, game: &Game, entities: &[Entity]) {
+    let mut lines = vec![String::new(), "YOU DIED".to_string(), String::new()];
+    lines.extend(morgue::death_summary(game, entities));
+    lines.push(String::new());
+    match highscore::record(game, entities) {
+        Ok(Some(rank)) => lines.push(format!("A new high score, number {}!", rank)),
+        Ok(None) => {}
+        Err(e) => lines.push(format!("Could not update the high scores: {}", e)),
+    }
+    match morgue::write_morgue(slot, game, entities) {
+        Ok(path) => lines.push(format!("The morgue file is {}", path.display())),
+        Err(e) => lines.push(format!("Could not write the morgue file: {}", e)),
+    }
+    if tcod.settings.permadeath {
+        match save::delete_save_file(slot) {
+            Ok(()) => lines.push("Your save is gone with you.".to_string()),
+            Err(e) => lines.push(format!("Could not delete the save: {}", e)),
+        }
+    }
+    lines.push(String::new());
+    msgbox(&lines.join("\n"), DEATH_SCREEN_WIDTH, &mut tcod.root);
+}
+
+// a failed autosave should not stop the game, it only shows up in the log
+fn autosave(
+    settings: &Settings,
+    slot: &str,
+    game: &mut Game,
+    entities: &[Entity],
+    replay: Option<&mut Replay>,
+) {
+    if let Err(e) = save_game(slot, settings.save_format, game, entities) {
+        game.messages.add(
+            format!("Autosave failed: {}", e),
+            color::RED,
+            Category::System,
+        );
+        return;
+    }
+    if let Some(replay) = replay {
+        if let Err(e) = write_replay(replay, slot, game, entities) {
+            game.messages.add(e, color::RED, Category::System);
+        }
+    }
+}
+
+// written right after every save so the recording always ends where the save does,
+// a game that crashed can be replayed up to its last save and recorded further
+fn write_replay(
+    replay: &mut Replay,
+    slot: &str,
+    game: &Game,
+    entities: &[Entity],
+) -> Result<(), String> {
+    replay.finish(game, entities);
+    let path = save::replay_path(slot);
+    replay.save(&path).map_err(|e| {
+        format!(
+            "The replay could not be written to {}: {}",
+            path.display(),
+            e
+        )
+    })
+}
+
+// watches a recorded game, Space pauses, Right or '.' plays a single action,
+// '+' and '-' change the speed and Escape quits
+fn play_replay(tcod: &mut Tcod, replay: Replay) {
+    let seed = replay.seed;
+    let (mut playback, mut game, mut entities) = Playback::new(replay);
+    game.messages.add(
+        format!(
+            "Replaying seed {}. Space pauses, Right steps, +/- change the speed.",
+            seed
+        ),
+        color::LIGHT_YELLOW,
+        Category::System,
+    );
+    let mut is_paused = false;
+    let mut speed = DEFAULT_REPLAY_SPEED;
+    let mut frame = 0;
+
+    while !tcod.root.window_closed() {
+        tcod.con.set_default_foreground(WHITE);
+        tcod.con.clear();
+
+        match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
+            Some((_, Event::Mouse(m))) => tcod.mouse = m,
+            Some((_, Event::Key(k))) => tcod.key = k,
+            _ => tcod.key = Default::default(),
+        }
+
+        render_all(tcod, &game, &entities);
+        let (played, total) = playback.progress();
+        let state = if is_paused { " (paused)" } else { "" };
+        tcod.root.set_default_foreground(LIGHT_GREY);
+        tcod.root.print_ex(
+            SCREEN_WIDTH - 1,
+            0,
+            BackgroundFlag::None,
+            TextAlignment::Right,
+            format!("Action {}/{}{}", played, total, state),
+        );
+        tcod.root.flush();
+
+        let mut do_step = false;
+        match (tcod.key.code, tcod.key.text()) {
+            (Escape, _) => break,
+            (Spacebar, _) => is_paused = !is_paused,
+            (Right, _) | (Text, ".") => do_step = true,
+            (Text, "+") => speed = (speed + 1).min(REPLAY_SPEEDS.len() - 1),
+            (Text, "-") => speed = speed.saturating_sub(1),
+            _ => {}
+        }
+
+        frame += 1;
+        let is_time_for_action = !is_paused && frame % REPLAY_SPEEDS[speed] == 0;
+        let did_step =
+            (do_step || is_time_for_action) && playback.step(&mut game, &mut entities).is_some();
+        if did_step && playback.is_finished() {
+            let (text, color) = match playback.matches_recording(&game, &entities) {
+                Some(true) => (
+                    "End of the replay, the game ended in the recorded state.",
+                    color::GREEN,
+                ),
+                Some(false) => (
+                    "End of the replay, the game did NOT end in the recorded state!",
+                    color::RED,
+                ),
+                None => (
+                    "End of the replay, it has no recorded state to compare.",
+                    color::LIGHT_YELLOW,
+                ),
+            };
+            game.messages.add(text, color, Category::System);
+        }
+    }
 }
 
-fn main_menu(tcod: &mut Tcod) {
+fn main_menu(tcod: &mut Tcod, mut command_line_seed: Option<u64>) {
     let img = tcod::image::Image::from_file("menu_background.png")
         .ok()
         .expect("menu_background.png not found");
@@ -189,25 +387,38 @@ fn main_menu(tcod: &mut Tcod) {
             "by Yours Truly",
         );
 
-        let choices = &["Play a new game", "Continue last game", "Quit"];
+        let choices = &[
+            "Play a new game",
+            "Play with a seed",
+            "Load a saved game",
+            "High scores",
+            "Quit",
+        ];
         let choice = menu("", choices, 24, &mut tcod.root);
 
         match choice {
             Some(0) => {
-                let (mut game, mut entities) = new_game(tcod);
-                play_game(tcod, &mut game, &mut entities);
+                // the command line seed is only used for the first game
+                let seed = command_line_seed.take().unwrap_or_else(rng::random_seed);
+                start_new_game(tcod, seed);
             }
-            Some(1) => match load_game() {
-                Ok((mut game, mut entities)) => {
-                    generate_fov_map(&mut tcod.fov, &mut game.map);
-                    play_game(tcod, &mut game, &mut entities);
+            Some(1) => {
+                let text = input_box("Enter a seed:\n", SEED_SCREEN_WIDTH, &mut tcod.root);
+                match text.as_deref().map(rng::parse_seed) {
+                    Some(Some(seed)) => start_new_game(tcod, seed),
+                    Some(None) => {
+                        msgbox(
+                            "\nA seed must be a whole positive number.\n",
+                            SEED_SCREEN_WIDTH,
+                            &mut tcod.root,
+                        );
+                    }
+                    None => {}
                 }
-                Err(_e) => {
-                    msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
-                    continue;
-                }
-            },
-            Some(2) => {
+            }
+            Some(2) => load_menu(tcod),
+            Some(3) => show_high_scores(tcod),
+            Some(4) => {
                 break;
             }
             _ => {}
@@ -215,67 +426,191 @@ fn main_menu(tcod: &mut Tcod) {
     }
 }
 
-fn make_map(entities: &mut Vec<Entity>) -> Map {
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
-
-    Room::generate_rooms(&mut map, entities);
+fn show_high_scores(tcod: &mut Tcod) {
+    let text = match HighScores::load() {
+        Ok(high_scores) if high_scores.is_empty() => "\nNobody has died yet.\n".to_string(),
+        Ok(high_scores) => {
+            let mut lines = vec![String::new(), "HIGH SCORES".to_string(), String::new()];
+            for (rank, high_score) in high_scores.iter().enumerate() {
+                lines.push(format!(
+                    "{:>2}. {:>6}  {}  seed {}",
+                    rank + 1,
+                    high_score.score,
+                    save::format_timestamp(high_score.date),
+                    high_score.seed
+                ));
+                lines.push(format!(
+                    "    level {}, {} on dungeon level {}",
+                    high_score.character_level, high_score.cause, high_score.dungeon_level
+                ));
+            }
+            lines.push(String::new());
+            lines.join("\n")
+        }
+        Err(e) => format!("\nCan not read the high scores:\n{}\n", e),
+    };
+    msgbox(&text, HIGH_SCORES_WIDTH, &mut tcod.root);
+}
 
-    map
+// asks for the name of the slot a new game is saved in, None if the player backed out
+fn choose_new_slot(tcod: &mut Tcod, seed: u64) -> Option<String> {
+    let text = input_box("Name of the save:\n", SAVE_MENU_WIDTH, &mut tcod.root)?;
+    let mut slot = save::sanitize_slot_name(&text);
+    if slot.is_empty() {
+        slot = format!("seed {}", seed);
+    }
+    if save::slot_exists(&slot) {
+        let header = format!("\nThere already is a save named \"{}\".\n", slot);
+        let choice = menu(
+            &header,
+            &["Overwrite it", "Cancel"],
+            SAVE_MENU_WIDTH,
+            &mut tcod.root,
+        );
+        if choice != Some(0) {
+            return None;
+        }
+    }
+    Some(slot)
 }
 
-fn render_all(
-    tcod: &mut Tcod,
-    game: &mut Game,
-    entities: &[Entity],
-    previous_player_position: (i32, i32),
-) {
-    let current_player_coordinates = entities[PLAYER].get_coordinates();
-    let do_calculate_fov = previous_player_position != current_player_coordinates;
-    if do_calculate_fov {
-        tcod.fov.compute_fov(
-            current_player_coordinates.0,
-            current_player_coordinates.1,
-            fov::TORCH_RADIUS,
-            fov::FOV_LIGHT_WALLS,
-            fov::FOV_ALGO,
+fn start_new_game(tcod: &mut Tcod, seed: u64) {
+    if let Some(slot) = choose_new_slot(tcod, seed) {
+        let generators = tcod.settings.generators.clone();
+        let (mut game, mut entities) = game::new_game_with_generators(seed, generators.clone());
+        play_game(
+            tcod,
+            &slot,
+            &mut game,
+            &mut entities,
+            Some(Replay::new(seed, generators)),
         );
     }
+}
+
+fn describe_slot(slot: &SaveSlot) -> String {
+    match &slot.meta {
+        Some(meta) => format!(
+            "{} - level {}, depth {}, turn {}, {}",
+            slot.name,
+            meta.character_level,
+            meta.dungeon_level,
+            meta.turn,
+            save::format_timestamp(meta.saved_at)
+        ),
+        None => format!("{} - unreadable", slot.name),
+    }
+}
+
+fn load_menu(tcod: &mut Tcod) {
+    loop {
+        let slots = save::list_saves();
+        if slots.is_empty() {
+            msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
+            return;
+        }
+        // the menu only has letters for 26 entries, the oldest saves are left out
+        let options: Vec<String> = slots.iter().take(26).map(describe_slot).collect();
+        let Some(choice) = menu(
+            "Choose a saved game:\n",
+            &options,
+            SAVE_MENU_WIDTH,
+            &mut tcod.root,
+        ) else {
+            return;
+        };
+        let slot = &slots[choice].name;
+        let header = format!("\n{}\n", slot);
+        match menu(&header, &["Continue", "Delete", "Back"], 24, &mut tcod.root) {
+            Some(0) => {
+                continue_game(tcod, slot);
+                return;
+            }
+            Some(1) => {
+                if let Err(e) = save::delete_save(slot) {
+                    let text = format!("\nThe save could not be deleted.\n\n{}\n", e);
+                    msgbox(&text, LOAD_ERROR_WIDTH, &mut tcod.root);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn continue_game(tcod: &mut Tcod, slot: &str) {
+    match load_game(slot) {
+        Ok((mut game, mut entities)) => {
+            // the recording can only go on if it ended where the save did
+            let replay = Replay::load(&save::replay_path(slot))
+                .ok()
+                .filter(|replay| replay.fits(&game, &entities));
+            if replay.is_none() {
+                game.messages.add(
+                    "No replay matches this save, the rest of the game is not recorded.",
+                    color::ORANGE,
+                    Category::System,
+                );
+            }
+            play_game(tcod, slot, &mut game, &mut entities, replay);
+        }
+        Err(e) => {
+            let text = match e {
+                SaveError::Missing => "\nNo saved game to load.\n".to_string(),
+                SaveError::Io(e) => format!("\nThe saved game can not be read.\n\n{}\n", e),
+                SaveError::Corrupt(reason) => format!(
+                    "\nThe saved game is damaged and can not be loaded.\n\n{}\n",
+                    reason
+                ),
+                SaveError::Incompatible { .. } => "\nThe saved game was made by a newer version \
+                    of the game, update the game to continue it.\n"
+                    .to_string(),
+                e @ SaveError::Encode(_) => format!("\n{}\n", e),
+            };
+            msgbox(&text, LOAD_ERROR_WIDTH, &mut tcod.root);
+        }
+    }
+}
+
+fn render_all(tcod: &mut Tcod, game: &Game, entities: &[Entity]) {
+    // draw the map, everything about a tile comes from its kind
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            let current_tile = &game.map[x as usize][y as usize];
+            if !current_tile.get_is_explored() {
+                continue;
+            }
+            let info = current_tile.get_kind().info();
+            let (background, foreground) = if game.fov.is_in_fov(x, y) {
+                (info.light, info.glyph_color)
+            } else {
+                (info.dark, info.glyph_color.lerp(info.dark, 0.5))
+            };
+
+            tcod.con
+                .set_char_background(x, y, to_tcod_color(background), BackgroundFlag::Set);
+            if info.glyph != ' ' {
+                tcod.con.set_default_foreground(to_tcod_color(foreground));
+                tcod.con.put_char(x, y, info.glyph, BackgroundFlag::None);
+            }
+        }
+    }
     // draw all entities from list
     let mut to_draw: Vec<_> = entities
         .iter()
         .filter(|e1| {
             let e_pos = e1.get_coordinates();
-            tcod.fov.is_in_fov(e_pos.0, e_pos.1)
+            game.fov.is_in_fov(e_pos.0, e_pos.1)
                 || (e1.is_always_visible()
                     && game.map[e_pos.0 as usize][e_pos.1 as usize].get_is_explored())
         })
         .collect();
     to_draw.sort_by(|o1, o2| o1.get_is_blocks().cmp(&o2.get_is_blocks()));
     for entity in to_draw {
-        entity.draw(&mut tcod.con);
-    }
-    // draw the map
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            let current_tile = &mut game.map[x as usize][y as usize];
-            let visible = tcod.fov.is_in_fov(x, y);
-            let wall = current_tile.get_is_block_sight();
-
-            let color = match (visible, wall) {
-                (false, false) => COLOR_DARK_GROUND,
-                (false, true) => COLOR_DARK_WALL,
-                (true, false) => COLOR_LIGHT_GROUND,
-                (true, true) => COLOR_LIGHT_WALL,
-            };
-
-            if visible {
-                current_tile.explore();
-            }
-            if current_tile.get_is_explored() {
-                tcod.con
-                    .set_char_background(x, y, color, BackgroundFlag::Set);
-            }
-        }
+        let (x, y) = entity.get_coordinates();
+        tcod.con
+            .set_default_foreground(to_tcod_color(entity.get_color()));
+        tcod.con
+            .put_char(x, y, entity.get_char(), BackgroundFlag::None);
     }
     // place all the Tile
     blit(
@@ -292,9 +627,8 @@ fn render_all(
     tcod.panel.set_default_background(BLACK);
     tcod.panel.clear();
 
-    let (hp, max_hp) = entities[PLAYER]
-        .get_fighter()
-        .map_or((0, 0), |f| f.get_hp());
+    let hp = entities[PLAYER].get_fighter().map_or(0, |f| f.get_hp().0);
+    let max_hp = entities[PLAYER].max_hp(game);
     render_bar(
         &mut tcod.panel,
         1,
@@ -315,24 +649,54 @@ fn render_all(
         format!("Dungeon level: {}", game.dungeon_level),
     );
 
+    // the player's status effects under the dungeon level, the last row sums up the rest
+    let effects: Vec<_> = entities[PLAYER].get_effects().iter().collect();
+    let rows = (PANEL_HEIGHT - 4) as usize;
+    for (row, status) in effects.iter().enumerate().take(rows) {
+        let y = 4 + row as i32;
+        if row == rows - 1 && effects.len() > rows {
+            tcod.panel.set_default_foreground(LIGHT_GREY);
+            tcod.panel.print_ex(
+                1,
+                y,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                format!("and {} more", effects.len() - row),
+            );
+            break;
+        }
+        let effect = status.get_effect();
+        tcod.panel
+            .set_default_foreground(to_tcod_color(effect.color()));
+        tcod.panel.print_ex(
+            1,
+            y,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            format!("{} ({})", effect.name(), status.get_turns()),
+        );
+    }
+
     tcod.panel.set_default_foreground(LIGHT_GREY);
     tcod.panel.print_ex(
         1,
         0,
         BackgroundFlag::None,
         TextAlignment::Left,
-        get_names_under_mouse(tcod.mouse, entities, &tcod.fov),
+        get_names_under_mouse(tcod.mouse, entities, &game.fov),
     );
 
     let mut y = MSG_HEIGHT as i32;
-    for &(ref msg, color) in game.messages.iter().rev() {
-        let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+    for message in game.messages.iter().rev() {
+        let msg = message.get_text();
+        let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, &msg);
         y -= msg_height;
         if y < 0 {
             break;
         }
-        tcod.panel.set_default_foreground(color);
-        tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+        tcod.panel
+            .set_default_foreground(to_tcod_color(message.get_color()));
+        tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, &msg);
     }
 
     blit(
@@ -346,109 +710,188 @@ fn render_all(
     );
 }
 
-fn player_controls(key: Key, game: &mut Game, entities: &mut Vec<Entity>, tcod: &mut Tcod) -> bool {
-    // charecter movement,
-    match (key, key.text(), entities[PLAYER].is_alive()) {
-        (Key { code: Up, .. }, _, _) => {
-            Entity::player_move_or_attack(PLAYER, 0, -1, game, entities);
-            true
-        }
-        (Key { code: Down, .. }, _, _) => {
-            Entity::player_move_or_attack(PLAYER, 0, 1, game, entities);
-            true
-        }
-        (Key { code: Left, .. }, _, _) => {
-            Entity::player_move_or_attack(PLAYER, -1, 0, game, entities);
-            true
-        }
-        (Key { code: Right, .. }, _, _) => {
-            Entity::player_move_or_attack(PLAYER, 1, 0, game, entities);
-            true
-        }
-        (Key { code: Text, .. }, "g", true) => {
-            let item_id = entities.iter().position(|entity| {
-                entity.get_coordinates() == entities[PLAYER].get_coordinates()
-                    && entity.get_item().is_some()
-            });
-            if let Some(item_id) = item_id {
-                Entity::pick_item_up(item_id, game, entities);
-            }
-            false
-        }
-        (Key { code: Text, .. }, "i", true) => {
-            let inventory_index = inventory_menu(
+// turns a key press into an action for the game core, screens that only
+// show something are handled right here
+fn player_controls(key: Key, game: &Game, entities: &[Entity], tcod: &mut Tcod) -> Option<Action> {
+    let command = tcod.bindings.command(key)?;
+    // charecter movement
+    if let Some((dx, dy)) = command.direction() {
+        return Some(Action::Move(dx, dy));
+    }
+    match (command, entities[PLAYER].is_alive()) {
+        (Command::Wait, _) => Some(Action::Wait),
+        (Command::PickUp, true) => Some(Action::PickUp),
+        (Command::Inventory, true) => {
+            let inventory_id = inventory_menu(
                 &game.inventory,
                 "Press the key next to an item or any ohter to close menu\n",
                 &mut tcod.root,
-            );
-            if let Some(inventory_index) = inventory_index {
-                use_item(inventory_index, tcod, game, entities);
-            }
-            false
-        }
-        (Key { code: Text, .. }, "d", true) => {
-            let inventory_index = inventory_menu(
-                &game.inventory,
-                "Press the key next to an item to drop it, or any ohter to close menu\n",
-                &mut tcod.root,
-            );
-            if let Some(inventory_index) = inventory_index {
-                Entity::drop_item(inventory_index, game, entities);
+            )?;
+            match game.inventory[inventory_id]
+                .get_item()
+                .and_then(Item::target_range)
+            {
+                Some(max_range) => {
+                    let (x, y) = target_tile(tcod, game, entities, max_range)?;
+                    Some(Action::UseAt(inventory_id, x, y))
+                }
+                None => Some(Action::Use(inventory_id)),
             }
-            false
         }
-        (Key { code: Text, .. }, "c", true) => {
+        (Command::Drop, true) => inventory_menu(
+            &game.inventory,
+            "Press the key next to an item to drop it, or any ohter to close menu\n",
+            &mut tcod.root,
+        )
+        .map(Action::Drop),
+        (Command::Character, true) => {
             let player = &entities[PLAYER];
             let level = player.get_level();
-            let level_up_xp = LEVEL_UP_BASE + level * LEVEL_UP_FACTOR;
+            let level_up_xp = player.level_up_xp();
             if let Some(fighter) = player.get_fighter().as_ref() {
+                // the effective stats with the base ones without equipment next to them
+                let equipped: String = player
+                    .equipped(game)
+                    .iter()
+                    .filter_map(|item| {
+                        let slot = item.get_equipment()?.slot;
+                        Some(format!(
+                            "\n                {}: {}",
+                            slot.name(),
+                            item.get_name()
+                        ))
+                    })
+                    .collect();
                 let msg = format!(
                     "Caracter information 
                 
                 Level: {}
                 Experience: {}
                 Experience to level up: {}
-                Maximum HP: {}
-                Attack: {}
-                Defence: {}
+                Maximum HP: {} (base {})
+                Attack: {} (base {})
+                Defence: {} (base {})
+
+                Equipment:{}
+
+                Seed: {}
                 ",
                     level,
                     fighter.get_xp(),
                     level_up_xp,
+                    player.max_hp(game),
                     fighter.get_hp().1,
+                    player.power(game),
                     fighter.get_power(),
-                    fighter.get_defence()
+                    player.defense(game),
+                    fighter.get_defence(),
+                    if equipped.is_empty() {
+                        " none"
+                    } else {
+                        &equipped
+                    },
+                    game.seed
                 );
                 msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
             }
-
-            false
+            None
+        }
+        (Command::Descend, _) => Some(Action::Descend),
+        (Command::Ascend, _) => Some(Action::Ascend),
+        (Command::UseStairs, _) => match game::stairs_under_player(entities) {
+            Some(Stairs::Down) => Some(Action::Descend),
+            Some(Stairs::Up) => Some(Action::Ascend),
+            None => None,
+        },
+        (Command::MessageLog, _) => {
+            log_viewer::show_message_log(
+                &mut tcod.root,
+                &game.messages,
+                &mut tcod.hidden_categories,
+            );
+            None
+        }
+        (Command::ShowBindings, _) => {
+            let text = tcod.bindings.describe();
+            msgbox(&text, KEYBINDINGS_SCREEN_WIDTH, &mut tcod.root);
+            None
         }
-        (Key { code: Text, .. }, "<", true) | (Key { code: Text, .. }, "e", true) => {
-            let is_player_on_stairs = entities.iter().any(|e| {
-                e.get_coordinates() == entities[PLAYER].get_coordinates()
-                    && e.get_name() == "stairs"
-            });
-            if is_player_on_stairs {
-                next_level(tcod, game, entities);
+        _ => None,
+    }
+}
+
+// picks a tile in view and in range with the mouse or the movement keys, a left click
+// or Enter chooses it and a right click or Escape cancels
+fn target_tile(
+    tcod: &mut Tcod,
+    game: &Game,
+    entities: &[Entity],
+    max_range: i32,
+) -> Option<(i32, i32)> {
+    // start on the closest monster, that is what most targets are
+    let mut cursor = Entity::closest_monster(&game.fov, entities, max_range)
+        .map_or(entities[PLAYER].get_coordinates(), |id| {
+            entities[id].get_coordinates()
+        });
+    while !tcod.root.window_closed() {
+        tcod.con.set_default_foreground(WHITE);
+        tcod.con.clear();
+        render_all(tcod, game, entities);
+        let (x, y) = cursor;
+        let is_valid = game::can_target(x, y, max_range, game, entities);
+        let background = if is_valid { LIGHT_YELLOW } else { DARKER_RED };
+        tcod.root
+            .set_char_background(x, y, background, BackgroundFlag::Set);
+        tcod.root.set_default_foreground(WHITE);
+        tcod.root.print_ex(
+            1,
+            0,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            "Choose a target with the mouse or the movement keys, Escape cancels",
+        );
+        tcod.root.flush();
+
+        match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
+            Some((_, Event::Mouse(m))) => {
+                tcod.mouse = m;
+                let (mx, my) = (m.cx as i32, m.cy as i32);
+                if mx < MAP_WIDTH && my < MAP_HEIGHT {
+                    cursor = (mx, my);
+                }
+                if m.rbutton_pressed {
+                    return None;
+                }
+                if m.lbutton_pressed && game::can_target(mx, my, max_range, game, entities) {
+                    return Some((mx, my));
+                }
             }
-            false
+            Some((_, Event::Key(key))) => match key.code {
+                Escape => return None,
+                Enter if is_valid => return Some(cursor),
+                _ => {
+                    let direction = tcod.bindings.command(key).and_then(Command::direction);
+                    if let Some((dx, dy)) = direction {
+                        cursor = (
+                            (x + dx).clamp(0, MAP_WIDTH - 1),
+                            (y + dy).clamp(0, MAP_HEIGHT - 1),
+                        );
+                    }
+                }
+            },
+            _ => {}
         }
-        _ => false,
     }
+    None
 }
 
-fn system_controls(key: Key, root: &mut Root) -> bool {
-    match key {
+fn system_controls(key: Key, bindings: &KeyBindings, root: &mut Root) -> bool {
+    match bindings.command(key) {
         // exit game
-        Key { code: Escape, .. } => true,
+        Some(Command::Exit) => true,
 
         // set fullscreen
-        Key {
-            code: Enter,
-            alt: true,
-            ..
-        } => {
+        Some(Command::Fullscreen) => {
             let is_fullscreen = root.is_fullscreen();
             root.set_fullscreen(!is_fullscreen);
             false
@@ -522,18 +965,104 @@ fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root)
     }
 }
 
+// asks which stat to raise once the player has enough experience
+fn level_up(tcod: &mut Tcod, entities: &[Entity]) -> Option<Stat> {
+    let player = &entities[PLAYER];
+    if !player.can_level_up() {
+        return None;
+    }
+    let fighter = player.get_fighter().unwrap();
+    let mut choice = None;
+    while choice.is_none() {
+        choice = menu(
+            "Level up! Choose a stat to raise:\n",
+            &[
+                format!("Constitution (+20 HP, from {})", fighter.get_hp().1),
+                format!("Strength (+1 attach, from {})", fighter.get_power()),
+                format!("Agility (+1 defence, from {})", fighter.get_defence()),
+            ],
+            LEVEL_SCREEN_WIDTH,
+            &mut tcod.root,
+        );
+    }
+    let stat = match choice.unwrap() {
+        0 => Stat::Constitution,
+        1 => Stat::Strength,
+        2 => Stat::Agility,
+        _ => unreachable!(),
+    };
+    Some(stat)
+}
+
 fn msgbox(text: &str, width: i32, root: &mut Root) {
     let options: &[&str] = &[];
     menu(text, options, width, root);
 }
 
+// simple single line text prompt, returns None if the player pressed Escape
+fn input_box(header: &str, width: i32, root: &mut Root) -> Option<String> {
+    let mut text = String::new();
+    loop {
+        let prompt = format!("{}> {}_", header, text);
+        let header_height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, &prompt);
+
+        let mut window = Offscreen::new(width, header_height);
+        window.set_default_foreground(WHITE);
+        window.print_rect_ex(
+            0,
+            0,
+            width,
+            header_height,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            &prompt,
+        );
+        let x = SCREEN_WIDTH / 2 - width / 2;
+        let y = SCREEN_HEIGHT / 2 - header_height / 2;
+        blit(
+            &window,
+            (0, 0),
+            (width, header_height),
+            root,
+            (x, y),
+            1.0,
+            0.7,
+        );
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        match key.code {
+            Enter => return Some(text),
+            Escape => return None,
+            Backspace => {
+                text.pop();
+            }
+            _ => {
+                // spaces too, slot names and searches can have several words
+                let is_text = key.printable.is_ascii_graphic() || key.printable == ' ';
+                if is_text && text.len() < width as usize - 3 {
+                    text.push(key.printable);
+                }
+            }
+        }
+        if root.window_closed() {
+            return None;
+        }
+    }
+}
+
 fn inventory_menu(inventory: &[Entity], header: &str, root: &mut Root) -> Option<usize> {
     let options = if inventory.len() == 0 {
         vec!["Inventory is empty.".into()]
     } else {
         inventory
             .iter()
-            .map(|item| item.get_name().clone())
+            .map(|item| match item.get_equipment() {
+                Some(equipment) if equipment.equipped => {
+                    format!("{} (on {})", item.get_name(), equipment.slot.name())
+                }
+                _ => item.get_name().clone(),
+            })
             .collect()
     };
     let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
@@ -544,69 +1073,3 @@ fn inventory_menu(inventory: &[Entity], header: &str, root: &mut Root) -> Option
         None
     }
 }
-
-fn use_item(inventory_id: usize, tcod: &Tcod, game: &mut Game, entities: &mut [Entity]) {
-    if let Some(item) = game.inventory[inventory_id].get_item() {
-        let on_use = match item {
-            Item::Heal => Entity::cast_heal,
-            Item::Lightning => Entity::cast_lightning,
-        };
-        match on_use(inventory_id, tcod, game, entities) {
-            UseResult::UsedUp => {
-                game.inventory.remove(inventory_id);
-            }
-            UseResult::Cancelled => {
-                game.messages.add("Cancelled", WHITE);
-            }
-        }
-    } else {
-        game.messages.add(
-            format!(
-                "The {} cannot be used.",
-                game.inventory[inventory_id].get_name()
-            ),
-            WHITE,
-        )
-    }
-}
-
-fn save_game(game: &Game, entities: &[Entity]) -> Result<(), Box<dyn std::error::Error>> {
-    let save_data = serde_json::to_string(&(game, entities))?;
-    let mut file = match std::fs::File::create("savegame.json") {
-        Ok(f) => f,
-        Err(e) => return Err(Box::new(e)),
-    };
-    file.write_all(save_data.as_bytes())?;
-    Ok(())
-}
-
-fn load_game() -> Result<(Game, Vec<Entity>), Box<dyn std::error::Error>> {
-    let mut save_state = String::new();
-    let mut file = std::fs::File::open("savegame.json")?;
-    file.read_to_string(&mut save_state)?;
-    let result = serde_json::from_str::<(Game, Vec<Entity>)>(&save_state)?;
-    Ok(result)
-}
-
-fn next_level(tcod: &mut Tcod, game: &mut Game, entities: &mut Vec<Entity>) {
-    game.messages.add(
-        "You take a moment to rest, and recover your strength.",
-        VIOLET,
-    );
-    let heal_hp_amount = entities[PLAYER]
-        .get_fighter()
-        .map_or(0, |f| f.get_hp().1 / 2);
-    entities[PLAYER].heal(heal_hp_amount);
-
-    game.messages.add(
-        "After a rare moment of peace you descend deeper into \
-    the heart of the dungeon...",
-        RED,
-    );
-    game.dungeon_level += 1;
-    assert_eq!(&entities[PLAYER] as *const _, &entities[0] as *const _);
-    // that will clear all the entities after Player, so it posible to save some
-    entities.truncate(PLAYER + 1);
-    game.map = make_map(entities);
-    generate_fov_map(&mut tcod.fov, &mut game.map);
-}
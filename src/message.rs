@@ -1,26 +1,302 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, LineWriter, Write},
+    path::PathBuf,
+};
+
 use serde::{Deserialize, Serialize};
-use tcod::Color;
 
-use crate::{BAR_WIDTH, PANEL_HEIGHT, SCREEN_WIDTH};
+use crate::color::{Color, RED};
+
+// messages kept in the log unless the settings say otherwise, older ones are dropped
+pub const DEFAULT_MESSAGE_CAPACITY: usize = 1000;
+
+// what a message is about, the message log can hide whole categories
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Category {
+    // attacks, kills and deaths
+    Combat,
+    // picking up, dropping and using items
+    Item,
+    // about the game rather than the dungeon, like saving and replays
+    System,
+    // the dungeon levels, their stairs and doors, and gaining character levels
+    Level,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Combat,
+        Category::Item,
+        Category::System,
+        Category::Level,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Combat => "combat",
+            Category::Item => "item",
+            Category::System => "system",
+            Category::Level => "level",
+        }
+    }
+}
 
-pub const MSG_X: i32 = BAR_WIDTH + 2;
-pub const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
-pub const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    text: String,
+    color: Color,
+    category: Category,
+    // the turn it was last added on, see `Messages::set_turn`
+    turn: u32,
+    // how many times in a row the same message was added
+    count: u32,
+}
+
+impl Message {
+    // the text with "(x3)" after it if it was repeated
+    pub fn get_text(&self) -> String {
+        if self.count > 1 {
+            format!("{} (x{})", self.text, self.count)
+        } else {
+            self.text.clone()
+        }
+    }
+    pub fn get_color(&self) -> Color {
+        self.color
+    }
+    pub fn get_category(&self) -> Category {
+        self.category
+    }
+    pub fn get_turn(&self) -> u32 {
+        self.turn
+    }
+    pub fn get_count(&self) -> u32 {
+        self.count
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct Messages {
-    messages: Vec<(String, Color)>,
+    // oldest first, never longer than `capacity`
+    messages: VecDeque<Message>,
+    // stamped on every message added from now on
+    #[serde(default)]
+    turn: u32,
+    // 0 keeps every message
+    #[serde(default = "default_capacity")]
+    capacity: usize,
+    // messages that no longer fit are appended here instead of being dropped
+    #[serde(skip)]
+    overflow_log: Option<OverflowLog>,
+}
+
+// kept open for as long as the game is played, every line is written out right away
+struct OverflowLog {
+    path: PathBuf,
+    file: LineWriter<File>,
 }
+
+fn default_capacity() -> usize {
+    DEFAULT_MESSAGE_CAPACITY
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Messages::new()
+    }
+}
+
 impl Messages {
     pub fn new() -> Self {
-        Self { messages: vec![] }
+        Self {
+            messages: VecDeque::new(),
+            turn: 0,
+            capacity: DEFAULT_MESSAGE_CAPACITY,
+            overflow_log: None,
+        }
+    }
+
+    // the same message right after itself only counts up instead of taking another line
+    pub fn add<T: Into<String>>(&mut self, message: T, color: Color, category: Category) {
+        let text = message.into();
+        if let Some(last) = self.messages.back_mut() {
+            if last.text == text && last.color == color && last.category == category {
+                last.count += 1;
+                last.turn = self.turn;
+                return;
+            }
+        }
+        self.messages.push_back(Message {
+            text,
+            color,
+            category,
+            turn: self.turn,
+            count: 1,
+        });
+        self.trim();
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.trim();
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // from now on messages that fall out of the log are written to the end of `path`
+    pub fn set_overflow_log(&mut self, path: PathBuf) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        self.overflow_log = Some(OverflowLog {
+            path,
+            file: LineWriter::new(file),
+        });
+        Ok(())
+    }
+
+    fn trim(&mut self) {
+        while self.capacity > 0 && self.messages.len() > self.capacity {
+            if let Some(message) = self.messages.pop_front() {
+                self.write_overflow(&message);
+            }
+        }
     }
 
-    pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.messages.push((message.into(), color))
+    // a log that can not be written to is given up on, the messages are just dropped then
+    fn write_overflow(&mut self, message: &Message) {
+        let Some(log) = &mut self.overflow_log else {
+            return;
+        };
+        let result = writeln!(log.file, "{:>5} {}", message.turn, message.get_text());
+        if let Err(e) = result {
+            let text = format!("Could not write to {}: {}", log.path.display(), e);
+            self.overflow_log = None;
+            self.add(text, RED, Category::System);
+        }
     }
 
-    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(String, Color)> {
+    pub fn set_turn(&mut self, turn: u32) {
+        self.turn = turn;
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Message> {
         self.messages.iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{GREEN, WHITE};
+
+    fn texts(messages: &Messages) -> Vec<String> {
+        messages.iter().map(Message::get_text).collect()
+    }
+
+    fn numbered(count: u32) -> Messages {
+        let mut messages = Messages::new();
+        for turn in 1..=count {
+            messages.set_turn(turn);
+            messages.add(format!("message {}", turn), WHITE, Category::System);
+        }
+        messages
+    }
+
+    #[test]
+    fn only_the_newest_messages_are_kept() {
+        let mut messages = numbered(5);
+        messages.set_capacity(3);
+        assert_eq!(texts(&messages), ["message 3", "message 4", "message 5"]);
+        messages.add("message 6", WHITE, Category::System);
+        assert_eq!(texts(&messages), ["message 4", "message 5", "message 6"]);
+        assert_eq!(messages.get_capacity(), 3);
+    }
+
+    #[test]
+    fn capacity_0_keeps_every_message() {
+        let mut messages = Messages::new();
+        messages.set_capacity(0);
+        let count = DEFAULT_MESSAGE_CAPACITY as u32 * 2;
+        for turn in 1..=count {
+            messages.add(format!("message {}", turn), WHITE, Category::System);
+        }
+        assert_eq!(messages.iter().count(), count as usize);
+        assert_eq!(messages.iter().next().unwrap().get_text(), "message 1");
+    }
+
+    #[test]
+    fn older_messages_go_to_the_overflow_log() {
+        let path = std::env::temp_dir()
+            .join("bug-free-fiesta-tests")
+            .join("logs")
+            .join("overflow.log");
+        let _ = fs::remove_file(&path);
+
+        let mut messages = numbered(2);
+        messages.set_overflow_log(path.clone()).unwrap();
+        messages.set_turn(2);
+        messages.add("message 2", WHITE, Category::System);
+        messages.set_capacity(1);
+        messages.set_turn(12);
+        messages.add("message 12", WHITE, Category::System);
+        messages.add("message 13", WHITE, Category::System);
+        assert_eq!(texts(&messages), ["message 13"]);
+
+        let log = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            log,
+            "    1 message 1\n    2 message 2 (x2)\n   12 message 12\n"
+        );
+        // the file is only appended to
+        messages.add("message 14", WHITE, Category::System);
+        assert!(fs::read_to_string(&path).unwrap().starts_with(&log));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn repeated_messages_count_up() {
+        let mut messages = Messages::new();
+        messages.set_turn(1);
+        messages.add("You hit the ork.", WHITE, Category::Combat);
+        messages.set_turn(2);
+        messages.add("You hit the ork.", WHITE, Category::Combat);
+        messages.add("You hit the ork.", WHITE, Category::Combat);
+        assert_eq!(texts(&messages), ["You hit the ork. (x3)"]);
+        let message = messages.iter().next().unwrap();
+        assert_eq!(message.get_count(), 3);
+        // stamped with the turn it was last added on
+        assert_eq!(message.get_turn(), 2);
+    }
+
+    #[test]
+    fn only_the_very_same_message_right_after_is_merged() {
+        let mut messages = Messages::new();
+        messages.add("You hit the ork.", WHITE, Category::Combat);
+        messages.add("You hit the ork.", GREEN, Category::Combat);
+        messages.add("You hit the ork.", GREEN, Category::System);
+        messages.add("The ork hits you.", GREEN, Category::System);
+        messages.add("You hit the ork.", GREEN, Category::System);
+        assert_eq!(messages.iter().count(), 5);
+        assert!(messages.iter().all(|message| message.get_count() == 1));
+        let categories: Vec<Category> = messages.iter().map(Message::get_category).collect();
+        assert_eq!(
+            categories,
+            [
+                Category::Combat,
+                Category::Combat,
+                Category::System,
+                Category::System,
+                Category::System
+            ]
+        );
+    }
+}
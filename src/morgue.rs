@@ -0,0 +1,146 @@
+use std::{collections::BTreeMap, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::{Entity, PLAYER},
+    game::Game,
+    save,
+};
+
+const MORGUE_DIR: &str = "morgue";
+// messages from the end of the log that go into the morgue file
+const MORGUE_MESSAGES: usize = 20;
+
+// what happened during a game besides its current state, for the death screen and the morgue file
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    // monsters the player killed by name
+    kills: BTreeMap<String, u32>,
+    death: Option<Death>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Death {
+    // finishes "You were ...", like "killed by an Ork"
+    pub cause: String,
+    // None if nobody did it
+    pub killer: Option<String>,
+}
+
+impl History {
+    pub fn record_kill(&mut self, name: &str) {
+        *self.kills.entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn record_death(&mut self, cause: String, killer: Option<String>) {
+        self.death = Some(Death { cause, killer });
+    }
+
+    pub fn killed_by(&mut self, killer: &str) {
+        let cause = format!("killed by {} {}", article(killer), killer);
+        self.record_death(cause, Some(killer.to_string()));
+    }
+
+    pub fn get_kills(&self) -> &BTreeMap<String, u32> {
+        &self.kills
+    }
+
+    pub fn total_kills(&self) -> u32 {
+        self.kills.values().sum()
+    }
+
+    pub fn get_death(&self) -> Option<&Death> {
+        self.death.as_ref()
+    }
+}
+
+fn article(name: &str) -> &'static str {
+    match name.chars().next().map(|c| c.to_ascii_lowercase()) {
+        Some('a' | 'e' | 'i' | 'o' | 'u') => "an",
+        _ => "a",
+    }
+}
+
+// the lines the death screen shows, the morgue file starts with them too
+pub fn death_summary(game: &Game, entities: &[Entity]) -> Vec<String> {
+    let player = &entities[PLAYER];
+    let cause = game
+        .history
+        .get_death()
+        .map_or("killed", |death| death.cause.as_str());
+    let mut lines = vec![
+        format!(
+            "You were {} on dungeon level {}.",
+            cause, game.dungeon_level
+        ),
+        String::new(),
+        format!("Killer: {}", killer_name(game)),
+        format!("Depth: {}", game.dungeon_level),
+        format!("Character level: {}", player.get_level()),
+        format!(
+            "Experience: {}",
+            player.get_fighter().map_or(0, |f| f.get_xp())
+        ),
+        format!("Turns: {}", game.turn),
+        String::new(),
+        format!("Kills: {}", game.history.total_kills()),
+    ];
+    lines.extend(
+        game.history
+            .get_kills()
+            .iter()
+            .map(|(name, count)| format!("  {} x{}", name, count)),
+    );
+    lines
+}
+
+fn killer_name(game: &Game) -> &str {
+    game.history
+        .get_death()
+        .and_then(|death| death.killer.as_deref())
+        .unwrap_or("nobody")
+}
+
+// the summary, the end of the message log and the inventory as plain text
+pub fn morgue_text(game: &Game, entities: &[Entity]) -> String {
+    let mut lines = vec![format!("Seed: {}", game.seed), String::new()];
+    lines.extend(death_summary(game, entities));
+
+    lines.push(String::new());
+    lines.push("Last messages:".to_string());
+    let messages: Vec<_> = game.messages.iter().rev().take(MORGUE_MESSAGES).collect();
+    lines.extend(
+        messages
+            .into_iter()
+            .rev()
+            .map(|message| format!("  {}", message.get_text())),
+    );
+
+    lines.push(String::new());
+    lines.push("Inventory:".to_string());
+    if game.inventory.is_empty() {
+        lines.push("  (empty)".to_string());
+    }
+    lines.extend(
+        game.inventory
+            .iter()
+            .map(|item| match item.get_equipment() {
+                Some(equipment) if equipment.equipped => {
+                    format!("  {} (on {})", item.get_name(), equipment.slot.name())
+                }
+                _ => format!("  {}", item.get_name()),
+            }),
+    );
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+// one file per death, named after the slot and the time so none gets overwritten
+pub fn write_morgue(slot: &str, game: &Game, entities: &[Entity]) -> io::Result<PathBuf> {
+    let path = save::data_dir()
+        .join(MORGUE_DIR)
+        .join(format!("{}-{}.txt", slot, save::now_secs()));
+    save::write_atomically(&path, morgue_text(game, entities).as_bytes())?;
+    Ok(path)
+}
@@ -0,0 +1,189 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{
+    entity::Entity,
+    tile::{Map, MAP_HEIGHT, MAP_WIDTH},
+};
+
+// how many tiles A* may expand before giving up, keeps monster turns cheap
+// when the target can not be reached or is far away on a big open level
+pub const PATH_SEARCH_BUDGET: usize = 2000;
+
+const DIRECTIONS: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+fn index(x: i32, y: i32) -> usize {
+    (x * MAP_HEIGHT + y) as usize
+}
+
+// diagonal steps cost the same as straight ones, so the chebyshev distance
+// never overestimates the cost of the remaining path
+fn heuristic((x, y): (i32, i32), (to_x, to_y): (i32, i32)) -> i32 {
+    (to_x - x).abs().max((to_y - y).abs())
+}
+
+// cheapest path from `from` to `to` moving in 8 directions and paying the movement
+// cost of every tile stepped on, `from` is not part of the returned path.
+// tiles with a blocking entity are avoided except `to`, which is usually
+// the target itself
+pub fn find_path(
+    from: (i32, i32),
+    to: (i32, i32),
+    map: &Map,
+    entities: &[Entity],
+    budget: usize,
+) -> Option<Vec<(i32, i32)>> {
+    let size = (MAP_WIDTH * MAP_HEIGHT) as usize;
+    let mut blocked = vec![false; size];
+    for entity in entities.iter().filter(|e| e.get_is_blocks()) {
+        let (x, y) = entity.get_coordinates();
+        blocked[index(x, y)] = true;
+    }
+
+    let mut cost = vec![i32::MAX; size];
+    let mut came_from = vec![None; size];
+    let mut open = BinaryHeap::new();
+    cost[index(from.0, from.1)] = 0;
+    open.push(Reverse((heuristic(from, to), 0, from)));
+
+    let mut expanded = 0;
+    while let Some(Reverse((_, current_cost, (x, y)))) = open.pop() {
+        if (x, y) == to {
+            let mut path = vec![to];
+            let mut step = to;
+            while let Some(previous) = came_from[index(step.0, step.1)] {
+                if previous == from {
+                    break;
+                }
+                path.push(previous);
+                step = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        // a cheaper way to this tile was already expanded
+        if current_cost > cost[index(x, y)] {
+            continue;
+        }
+        expanded += 1;
+        if expanded > budget {
+            return None;
+        }
+
+        for (dx, dy) in DIRECTIONS {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= MAP_WIDTH || ny >= MAP_HEIGHT {
+                continue;
+            }
+            let tile = &map[nx as usize][ny as usize];
+            let next = index(nx, ny);
+            if !tile.get_is_passable() || (blocked[next] && (nx, ny) != to) {
+                continue;
+            }
+            let next_cost = current_cost + tile.get_movement_cost();
+            if next_cost < cost[next] {
+                cost[next] = next_cost;
+                came_from[next] = Some((x, y));
+                open.push(Reverse((
+                    next_cost + heuristic((nx, ny), to),
+                    next_cost,
+                    (nx, ny),
+                )));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::WHITE,
+        tile::{Tile, TileKind},
+    };
+
+    // all walls except the floor rectangle from (1, 1) to (width, height)
+    fn open_room(width: i32, height: i32) -> Map {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for x in 1..=width {
+            for y in 1..=height {
+                map[x as usize][y as usize] = Tile::empty();
+            }
+        }
+        map
+    }
+
+    // every step is next to the one before and can be walked on
+    fn assert_walkable(from: (i32, i32), path: &[(i32, i32)], map: &Map) {
+        let mut previous = from;
+        for &(x, y) in path {
+            assert!(
+                heuristic(previous, (x, y)) == 1,
+                "{:?} jumps to {:?}",
+                previous,
+                (x, y)
+            );
+            assert!(map[x as usize][y as usize].get_is_passable());
+            previous = (x, y);
+        }
+    }
+
+    #[test]
+    fn goes_around_walls() {
+        let mut map = open_room(9, 5);
+        // a wall down the middle with a gap at the bottom
+        for tile in map[5][1..=4].iter_mut() {
+            *tile = Tile::wall();
+        }
+        let path = find_path((2, 1), (8, 1), &map, &[], PATH_SEARCH_BUDGET).unwrap();
+        assert_eq!(path.last(), Some(&(8, 1)));
+        assert!(path.contains(&(5, 5)));
+        assert_walkable((2, 1), &path, &map);
+    }
+
+    #[test]
+    fn goes_around_blocking_entities() {
+        let map = open_room(9, 3);
+        let ork = Entity::new(5, 2, 'o', WHITE, "Ork", true);
+        let target = Entity::new(8, 2, '@', WHITE, "Player", true);
+        let entities = [ork, target];
+        let path = find_path((2, 2), (8, 2), &map, &entities, PATH_SEARCH_BUDGET).unwrap();
+        assert!(!path.contains(&(5, 2)));
+        // the target blocks too but is where the path ends
+        assert_eq!(path.last(), Some(&(8, 2)));
+        assert_eq!(path.len(), 6);
+        assert_walkable((2, 2), &path, &map);
+    }
+
+    #[test]
+    fn prefers_cheaper_tiles() {
+        let mut map = open_room(9, 3);
+        for column in map[3..=7].iter_mut() {
+            column[2] = Tile::new(TileKind::Water);
+        }
+        let path = find_path((1, 2), (9, 2), &map, &[], PATH_SEARCH_BUDGET).unwrap();
+        // around the water is as many steps as through it
+        assert_eq!(path.len(), 8);
+        assert!(path
+            .iter()
+            .all(|&(x, y)| map[x as usize][y as usize].get_kind() != TileKind::Water));
+        assert_walkable((1, 2), &path, &map);
+    }
+
+    #[test]
+    fn gives_up_once_the_budget_runs_out() {
+        let map = open_room(MAP_WIDTH - 2, MAP_HEIGHT - 2);
+        let (from, to) = ((1, 1), (MAP_WIDTH - 2, MAP_HEIGHT - 2));
+        assert_eq!(find_path(from, to, &map, &[], 10), None);
+        assert!(find_path(from, to, &map, &[], PATH_SEARCH_BUDGET).is_some());
+    }
+}
@@ -0,0 +1,199 @@
+use std::{io::Read, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    action::{self, Action, TurnOutcome},
+    entity::Entity,
+    game::{self, Game, MapGenerator},
+    save,
+};
+
+// a game is fully described by its seed and the actions the player took,
+// all randomness comes from the seeded `GameRng` so playing the actions
+// again on a new game with the same seed ends in the same state
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    // the generators from the settings the game was started with, see `Game::generators`
+    #[serde(default)]
+    pub generators: Vec<MapGenerator>,
+    pub actions: Vec<Action>,
+    // `state_hash` of the game once the last action was applied
+    pub final_state: Option<u64>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, generators: Vec<MapGenerator>) -> Self {
+        Replay {
+            seed,
+            generators,
+            actions: vec![],
+            final_state: None,
+        }
+    }
+
+    pub fn record(&mut self, action: Action) {
+        self.actions.push(action);
+    }
+
+    // a state that can not be hashed is left out, the replay can still be watched
+    pub fn finish(&mut self, game: &Game, entities: &[Entity]) {
+        self.final_state = state_hash(game, entities).ok();
+    }
+
+    // true if the recording ended in this exact state, so new actions can be appended to it
+    pub fn fits(&self, game: &Game, entities: &[Entity]) -> bool {
+        self.seed == game.seed
+            && self.generators == game.generators
+            && self.final_state.is_some()
+            && self.final_state == state_hash(game, entities).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let replay_data = serde_json::to_string(self)?;
+        save::write_atomically(path, replay_data.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, Box<dyn std::error::Error>> {
+        let mut replay_data = String::new();
+        let mut file = std::fs::File::open(path)?;
+        file.read_to_string(&mut replay_data)?;
+        Ok(serde_json::from_str::<Replay>(&replay_data)?)
+    }
+}
+
+// FNV-1a over the saved form of the game, stable between runs and compilers
+// unlike the hasher of the standard library. The message log is left out, the
+// front-end adds its own messages and how many are kept is a setting
+pub fn state_hash(game: &Game, entities: &[Entity]) -> Result<u64, serde_json::Error> {
+    // through bytes instead of `to_value`, that can not hold the u128 in the rng
+    let state = serde_json::to_vec(&(game, entities))?;
+    let mut state: Value = serde_json::from_slice(&state)?;
+    if let Some(game) = state[0].as_object_mut() {
+        game.remove("messages");
+    }
+    let state = serde_json::to_vec(&state)?;
+    Ok(state.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    }))
+}
+
+// plays a replay one action at a time on a freshly generated game
+pub struct Playback {
+    replay: Replay,
+    next_action: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> (Self, Game, Vec<Entity>) {
+        let (game, entities) =
+            game::new_game_with_generators(replay.seed, replay.generators.clone());
+        let playback = Playback {
+            replay,
+            next_action: 0,
+        };
+        (playback, game, entities)
+    }
+
+    // applies the next recorded action, None once all of them were played
+    pub fn step(&mut self, game: &mut Game, entities: &mut Vec<Entity>) -> Option<TurnOutcome> {
+        let action = *self.replay.actions.get(self.next_action)?;
+        self.next_action += 1;
+        Some(action::apply_action(game, entities, action))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_action >= self.replay.actions.len()
+    }
+
+    // (played, total) actions
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next_action, self.replay.actions.len())
+    }
+
+    // None if the recording did not store its final state
+    pub fn matches_recording(&self, game: &Game, entities: &[Entity]) -> Option<bool> {
+        let final_state = self.replay.final_state?;
+        Some(state_hash(game, entities).is_ok_and(|state| state == final_state))
+    }
+}
+
+pub fn replay_from_args() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--replay" {
+            return args.next();
+        }
+        if let Some(value) = arg.strip_prefix("--replay=") {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_hash_of_a_new_game() {
+        for seed in 0..5 {
+            let (game, entities) = game::new_game(seed);
+            let hash = state_hash(&game, &entities).expect("a new game can be hashed");
+            let (game, entities) = game::new_game(seed);
+            assert_eq!(state_hash(&game, &entities).ok(), Some(hash));
+        }
+    }
+
+    #[test]
+    fn a_replay_ends_in_the_recorded_state() {
+        let actions = [
+            Action::Move(1, 0),
+            Action::Move(0, 1),
+            Action::PickUp,
+            Action::Move(-1, 0),
+            Action::Use(0),
+            Action::Move(0, -1),
+            Action::Wait,
+            Action::Move(1, 1),
+        ];
+        let (mut game, mut entities) = game::new_game(13);
+        let mut replay = Replay::new(13, vec![]);
+        for &action in actions.iter().cycle().take(300) {
+            replay.record(action);
+            action::apply_action(&mut game, &mut entities, action);
+        }
+        replay.finish(&game, &entities);
+        assert!(replay.fits(&game, &entities));
+
+        let (mut playback, mut played_game, mut played_entities) = Playback::new(replay);
+        while playback
+            .step(&mut played_game, &mut played_entities)
+            .is_some()
+        {}
+        assert_eq!(playback.progress(), (300, 300));
+        assert_eq!(
+            state_hash(&played_game, &played_entities).unwrap(),
+            state_hash(&game, &entities).unwrap()
+        );
+        assert_eq!(
+            playback.matches_recording(&played_game, &played_entities),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn messages_do_not_change_the_state_hash() {
+        let (mut game, entities) = game::new_game(3);
+        let hash = state_hash(&game, &entities).unwrap();
+        game.messages.add(
+            "Replaying seed 3",
+            crate::color::WHITE,
+            crate::message::Category::System,
+        );
+        assert_eq!(state_hash(&game, &entities).unwrap(), hash);
+    }
+}
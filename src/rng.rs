@@ -0,0 +1,32 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// every random roll in the game (map generation, spawning, combat) goes through
+// this generator, so the same seed always produces the same dungeon
+pub type GameRng = ChaCha8Rng;
+
+pub fn new_rng(seed: u64) -> GameRng {
+    GameRng::seed_from_u64(seed)
+}
+
+// only used to pick a seed when the player did not provide one
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen()
+}
+
+pub fn parse_seed(text: &str) -> Option<u64> {
+    text.trim().parse::<u64>().ok()
+}
+
+pub fn seed_from_args() -> Option<u64> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            return args.next().as_deref().and_then(parse_seed);
+        }
+        if let Some(value) = arg.strip_prefix("--seed=") {
+            return parse_seed(value);
+        }
+    }
+    None
+}
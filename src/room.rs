@@ -1,17 +1,21 @@
 use std::cmp;
 
 use rand::Rng;
-use tcod::colors::WHITE;
 
 use crate::{
-    entity::{Entity, FREDERIC, PLAYER},
-    tile::{Map, Tile, MAP_HEIGHT, MAP_WIDTH},
+    entity::{Entity, Stairs, FREDERIC, PLAYER},
+    rng::GameRng,
+    tile::{Map, Tile, TileKind, MAP_HEIGHT, MAP_WIDTH},
 };
 
-const ROOM_MAX_SIZE: i32 = 10;
-const ROOM_MIN_SIZE: i32 = 6;
+pub const ROOM_MAX_SIZE: i32 = 10;
+pub const ROOM_MIN_SIZE: i32 = 6;
 const MAX_ROOMS: i32 = 30; //30
 
+// chance for a tunnel entering a room to get a door, and for that door to be locked
+const DOOR_CHANCE: f64 = 0.7;
+const LOCKED_DOOR_CHANCE: f64 = 0.15;
+
 pub struct Room {
     x1: i32,
     y1: i32,
@@ -61,21 +65,125 @@ impl Room {
             map[l as usize][c as usize] = Tile::empty();
         }
     }
-    pub fn generate_rooms(map: &mut Map, entities: &mut Vec<Entity>) {
+    // tiles of the room walls a tunnel goes through, a doorway needs wall on both
+    // sides so tunnels running along the wall do not count
+    fn doorways(&self, map: &Map) -> Vec<(i32, i32)> {
+        let is_kind = |x: i32, y: i32, kind| map[x as usize][y as usize].get_kind() == kind;
+        let mut doorways = vec![];
+        for x in (self.x1 + 1)..self.x2 {
+            for y in [self.y1, self.y2] {
+                if is_kind(x, y, TileKind::Floor)
+                    && is_kind(x - 1, y, TileKind::Wall)
+                    && is_kind(x + 1, y, TileKind::Wall)
+                {
+                    doorways.push((x, y));
+                }
+            }
+        }
+        for y in (self.y1 + 1)..self.y2 {
+            for x in [self.x1, self.x2] {
+                if is_kind(x, y, TileKind::Floor)
+                    && is_kind(x, y - 1, TileKind::Wall)
+                    && is_kind(x, y + 1, TileKind::Wall)
+                {
+                    doorways.push((x, y));
+                }
+            }
+        }
+        doorways
+    }
+
+    // has to run after all the tunnels are dug
+    pub fn place_doors(rooms: &[Room], map: &mut Map, rng: &mut GameRng) {
+        for room in rooms {
+            for (x, y) in room.doorways(map) {
+                if !rng.gen_bool(DOOR_CHANCE) {
+                    continue;
+                }
+                let kind = if rng.gen_bool(LOCKED_DOOR_CHANCE) {
+                    TileKind::DoorLocked
+                } else {
+                    TileKind::DoorClosed
+                };
+                map[x as usize][y as usize].set_kind(kind);
+            }
+        }
+    }
+
+    // one key for every locked door, all of them somewhere the player can get to
+    // without going through a locked door
+    fn place_keys(map: &Map, entities: &mut Vec<Entity>, rng: &mut GameRng) {
+        let locked_doors = map
+            .iter()
+            .flatten()
+            .filter(|tile| tile.get_kind() == TileKind::DoorLocked)
+            .count();
+        if locked_doors == 0 {
+            return;
+        }
+        let (x, y) = entities[PLAYER].get_coordinates();
+        let mut free_tiles: Vec<(i32, i32)> = Tile::flood_fill_with(x, y, map, |tile| {
+            tile.get_is_passable() || tile.get_kind() == TileKind::DoorClosed
+        })
+        .into_iter()
+        .filter(|&(x, y)| {
+            map[x as usize][y as usize].get_is_passable()
+                && !entities.iter().any(|e| e.get_coordinates() == (x, y))
+        })
+        .collect();
+
+        for _ in 0..locked_doors.min(free_tiles.len()) {
+            let (x, y) = free_tiles.swap_remove(rng.gen_range(0..free_tiles.len()));
+            entities.push(Entity::new_key(x, y));
+        }
+    }
+
+    // joins two points with an L-shaped tunnel
+    pub fn connect(from: (i32, i32), to: (i32, i32), map: &mut Map, rng: &mut GameRng) {
+        let (prev_x, prev_y) = from;
+        let (new_x, new_y) = to;
+        // simulate coin flip
+        let is_horizontal = rng.gen::<bool>();
+        if is_horizontal {
+            Room::create_h_tunel(prev_x, new_x, prev_y, map);
+            Room::create_v_tunel(prev_y, new_y, new_x, map);
+        } else {
+            Room::create_v_tunel(prev_y, new_y, prev_x, map);
+            Room::create_h_tunel(prev_x, new_x, new_y, map);
+        }
+    }
+
+    // the player and Frederic start in the first room, the stairs go to the last one
+    // and every other room gets monsters and items
+    pub fn place_entities(
+        rooms: &mut [Room],
+        map: &Map,
+        entities: &mut Vec<Entity>,
+        rng: &mut GameRng,
+    ) {
+        for room in rooms.iter_mut().skip(1) {
+            Entity::populate_room(room, map, entities, rng);
+        }
+
+        let (player_x, player_y) = rooms[0].get_center();
+        let (last_room_x, last_room_y) = rooms[rooms.len() - 1].get_center();
+        entities.push(Entity::new_stairs(last_room_x, last_room_y, Stairs::Down));
+        entities[PLAYER].set_position(player_x, player_y);
+        entities[FREDERIC].set_position(player_x - 2, player_y);
+        Room::place_keys(map, entities, rng);
+    }
+
+    pub fn generate_rooms(map: &mut Map, entities: &mut Vec<Entity>, rng: &mut GameRng) {
         let mut rooms = Vec::<Room>::new();
-        let mut player_x: i32 = 25;
-        let mut player_y: i32 = 23;
-        let mut frederic_x = -1;
-        let mut frederic_y = -1;
 
         for _ in 0..MAX_ROOMS {
             // random width and height
-            let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE..=ROOM_MAX_SIZE);
-            let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE..=ROOM_MAX_SIZE);
+            let w = rng.gen_range(ROOM_MIN_SIZE..=ROOM_MAX_SIZE);
+            let h = rng.gen_range(ROOM_MIN_SIZE..=ROOM_MAX_SIZE);
             // random possition walidating screen boarders
-            let x = rand::thread_rng().gen_range(0..MAP_WIDTH - w);
-            let y = rand::thread_rng().gen_range(0..MAP_HEIGHT - h);
-            let mut new_room = Room::new(x, y, w, h);
+            let x = rng.gen_range(0..MAP_WIDTH - w);
+            let y = rng.gen_range(0..MAP_HEIGHT - h);
+            let new_room = Room::new(x, y, w, h);
 
             let failed = rooms.iter().any(|other_room| new_room.includes(other_room));
 
@@ -83,36 +191,13 @@ impl Room {
                 // if no intersections, it is a valid room
                 new_room.put_on_map(map);
 
-                let (new_x, new_y) = new_room.get_center();
-
-                if rooms.is_empty() {
-                    // first room, and player coordiantes
-                    player_x = new_x;
-                    player_y = new_y;
-                    frederic_x = new_x - 2;
-                    frederic_y = new_y;
-                } else {
-                    let (prev_x, prev_y) = rooms[rooms.len() - 1].get_center();
-
-                    // simulate coin flip
-                    let is_horizontal = rand::random::<bool>();
-                    if is_horizontal {
-                        Room::create_h_tunel(prev_x, new_x, prev_y, map);
-                        Room::create_v_tunel(prev_y, new_y, new_x, map);
-                    } else {
-                        Room::create_v_tunel(prev_y, new_y, prev_x, map);
-                        Room::create_h_tunel(prev_x, new_x, new_y, map);
-                    }
-                    Entity::populate_room(&mut new_room, map, entities);
+                if let Some(prev_room) = rooms.last() {
+                    Room::connect(prev_room.get_center(), new_room.get_center(), map, rng);
                 }
                 rooms.push(new_room);
             }
         }
-        let (last_room_x, last_room_y) = rooms[rooms.len() - 1].get_center();
-        let mut stairs = Entity::new(last_room_x, last_room_y, '<', WHITE, "stairs", false);
-        stairs.make_always_visible();
-        entities.push(stairs);
-        entities[PLAYER].set_position(player_x, player_y);
-        entities[FREDERIC].set_position(frederic_x, frederic_y);
+        Room::place_doors(&rooms, map, rng);
+        Room::place_entities(&mut rooms, map, entities, rng);
     }
 }
@@ -0,0 +1,750 @@
+use std::{
+    env, fmt, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    entity::{Entity, PLAYER},
+    fov::generate_fov_map,
+    game::Game,
+    rng,
+};
+
+const GAME_DIR: &str = "bug-free-fiesta";
+const SAVES_DIR: &str = "saves";
+const REPLAYS_DIR: &str = "replays";
+const MESSAGE_LOGS_DIR: &str = "logs";
+const REPLAY_EXTENSION: &str = "json";
+// where the game saved before there were slots, next to the game itself
+const LEGACY_SAVE: &str = "savegame.json";
+const LEGACY_SLOT: &str = "savegame";
+
+// what new saves are written as, older saves are read in whatever format they have
+pub const DEFAULT_SAVE_FORMAT: SaveFormat = SaveFormat::CborGz;
+
+// bump this whenever the saved form of `Game`, `Entity` or anything inside them changes
+// and add the migration from the previous version to `MIGRATIONS`
+pub const SAVE_VERSION: u32 = 2;
+
+// `MIGRATIONS[n]` turns a version n save into a version n + 1 save
+const MIGRATIONS: [fn(Value) -> Result<Value, SaveError>; SAVE_VERSION as usize] =
+    [migrate_v0, migrate_v1];
+
+#[derive(Debug)]
+pub enum SaveError {
+    // there is no save file
+    Missing,
+    // the file exists but can not be read
+    Io(io::Error),
+    // the file is not a save or got damaged
+    Corrupt(String),
+    // written by a newer version of the game
+    Incompatible { version: u32 },
+    // the game could not be turned into a save
+    Encode(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Missing => write!(f, "there is no saved game"),
+            SaveError::Io(e) => write!(f, "the saved game can not be read: {}", e),
+            SaveError::Corrupt(reason) => write!(f, "the saved game is damaged: {}", reason),
+            SaveError::Incompatible { version } => write!(
+                f,
+                "the saved game has format version {} but this game only reads up to {}",
+                version, SAVE_VERSION
+            ),
+            SaveError::Encode(reason) => write!(f, "the game can not be saved: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::NotFound {
+            SaveError::Missing
+        } else {
+            SaveError::Io(e)
+        }
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(e: serde_json::Error) -> Self {
+        SaveError::Corrupt(e.to_string())
+    }
+}
+
+// how a save is encoded on disk, picked from the file extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SaveFormat {
+    // readable, handy for debugging
+    Json,
+    JsonGz,
+    // binary but self-describing, so migrations still see field names
+    Cbor,
+    CborGz,
+}
+
+impl SaveFormat {
+    pub const ALL: [SaveFormat; 4] = [
+        SaveFormat::Json,
+        SaveFormat::JsonGz,
+        SaveFormat::Cbor,
+        SaveFormat::CborGz,
+    ];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            SaveFormat::Json => "json",
+            SaveFormat::JsonGz => "json.gz",
+            SaveFormat::Cbor => "cbor",
+            SaveFormat::CborGz => "cbor.gz",
+        }
+    }
+
+    // None if the file name ends in none of the known extensions
+    pub fn from_path(path: &Path) -> Option<SaveFormat> {
+        let name = path.file_name()?.to_str()?;
+        SaveFormat::ALL
+            .into_iter()
+            .find(|format| name.ends_with(&format!(".{}", format.extension())))
+    }
+
+    fn is_compressed(self) -> bool {
+        matches!(self, SaveFormat::JsonGz | SaveFormat::CborGz)
+    }
+
+    fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, SaveError> {
+        let mut data = vec![];
+        match self {
+            SaveFormat::Json | SaveFormat::JsonGz => serde_json::to_writer(&mut data, value)
+                .map_err(|e| SaveError::Encode(e.to_string()))?,
+            SaveFormat::Cbor | SaveFormat::CborGz => ciborium::into_writer(value, &mut data)
+                .map_err(|e| SaveError::Encode(e.to_string()))?,
+        }
+        if self.is_compressed() {
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            encoder.write_all(&data)?;
+            data = encoder.finish()?;
+        }
+        Ok(data)
+    }
+
+    // decodes into a plain value so the save can be migrated before it is turned into a game
+    fn decode(self, data: &[u8]) -> Result<Value, SaveError> {
+        let mut inflated = vec![];
+        let data = if self.is_compressed() {
+            GzDecoder::new(data)
+                .read_to_end(&mut inflated)
+                .map_err(|e| SaveError::Corrupt(e.to_string()))?;
+            &inflated[..]
+        } else {
+            data
+        };
+        match self {
+            SaveFormat::Json | SaveFormat::JsonGz => Ok(serde_json::from_slice(data)?),
+            SaveFormat::Cbor | SaveFormat::CborGz => {
+                ciborium::from_reader(data).map_err(|e| SaveError::Corrupt(e.to_string()))
+            }
+        }
+    }
+}
+
+// what the load menu shows about a save without loading the whole game
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SaveMeta {
+    pub character_level: i32,
+    pub dungeon_level: u32,
+    pub turn: u32,
+    // see `now_secs`
+    pub saved_at: u64,
+}
+
+impl SaveMeta {
+    fn new(game: &Game, entities: &[Entity]) -> Self {
+        SaveMeta {
+            character_level: entities[PLAYER].get_level(),
+            dungeon_level: game.dungeon_level,
+            turn: game.turn,
+            saved_at: now_secs(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SaveSlot {
+    pub name: String,
+    // None if the save is damaged or too old to have it
+    pub meta: Option<SaveMeta>,
+}
+
+#[derive(Serialize)]
+struct SaveRef<'a> {
+    version: u32,
+    meta: SaveMeta,
+    game: &'a Game,
+    entities: &'a [Entity],
+}
+
+#[derive(Deserialize)]
+struct SaveData {
+    game: Game,
+    entities: Vec<Entity>,
+}
+
+#[derive(Deserialize)]
+struct SaveHeader {
+    meta: Option<SaveMeta>,
+}
+
+// seconds since the unix epoch, what every file of the game stores times as.
+// 0 if the clock is set before 1970
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+// %APPDATA% on windows, ~/Library/Application Support on macOS and
+// $XDG_DATA_HOME or ~/.local/share everywhere else
+pub fn data_dir() -> PathBuf {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".local").join("share")))
+    };
+    base.unwrap_or_else(|| PathBuf::from(".")).join(GAME_DIR)
+}
+
+fn save_path(slot: &str, format: SaveFormat) -> PathBuf {
+    data_dir()
+        .join(SAVES_DIR)
+        .join(format!("{}.{}", slot, format.extension()))
+}
+
+// the file a slot was saved to, whatever its format
+fn find_save(slot: &str) -> Option<PathBuf> {
+    SaveFormat::ALL
+        .into_iter()
+        .map(|format| save_path(slot, format))
+        .find(|path| path.exists())
+}
+
+// the recording of the game in a slot, see `replay::Replay`
+pub fn replay_path(slot: &str) -> PathBuf {
+    data_dir()
+        .join(REPLAYS_DIR)
+        .join(slot)
+        .with_extension(REPLAY_EXTENSION)
+}
+
+// where messages that no longer fit in the log of a slot go, see `Messages::set_overflow_log`
+pub fn message_log_path(slot: &str) -> PathBuf {
+    data_dir()
+        .join(MESSAGE_LOGS_DIR)
+        .join(slot)
+        .with_extension("log")
+}
+
+// slot names become file names, so only a safe set of characters is kept
+pub fn sanitize_slot_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        .collect()
+}
+
+pub fn slot_exists(slot: &str) -> bool {
+    find_save(slot).is_some()
+}
+
+// writes next to the target first and renames it over, a crash halfway
+// through leaves the old file as it was
+pub fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp_path = path.with_extension("tmp");
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+// a slot only ever has one file, saving in a new format removes the old one
+pub fn save_game(
+    slot: &str,
+    format: SaveFormat,
+    game: &Game,
+    entities: &[Entity],
+) -> Result<(), SaveError> {
+    write_save(&save_path(slot, format), game, entities)?;
+    for other in SaveFormat::ALL.into_iter().filter(|&other| other != format) {
+        remove_if_exists(&save_path(slot, other))?;
+    }
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), SaveError> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(SaveError::Io(e)),
+        _ => Ok(()),
+    }
+}
+
+// saves to any path, the format comes from its extension
+pub fn write_save(path: &Path, game: &Game, entities: &[Entity]) -> Result<(), SaveError> {
+    let format = format_of(path)?;
+    let save = SaveRef {
+        version: SAVE_VERSION,
+        meta: SaveMeta::new(game, entities),
+        game,
+        entities,
+    };
+    write_atomically(path, &format.encode(&save)?)?;
+    Ok(())
+}
+
+fn format_of(path: &Path) -> Result<SaveFormat, SaveError> {
+    SaveFormat::from_path(path)
+        .ok_or_else(|| SaveError::Corrupt(format!("{} is not a known save format", path.display())))
+}
+
+fn read_value(path: &Path) -> Result<Value, SaveError> {
+    let format = format_of(path)?;
+    format.decode(&fs::read(path)?)
+}
+
+// most recently saved first
+pub fn list_saves() -> Vec<SaveSlot> {
+    let Ok(dir) = fs::read_dir(data_dir().join(SAVES_DIR)) else {
+        return vec![];
+    };
+    let mut slots: Vec<SaveSlot> = dir
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let format = SaveFormat::from_path(&path)?;
+            let file_name = path.file_name()?.to_str()?;
+            let name = file_name
+                .strip_suffix(format.extension())?
+                .strip_suffix('.')?
+                .to_string();
+            let meta = read_value(&path)
+                .ok()
+                .and_then(|save| serde_json::from_value::<SaveHeader>(save).ok())
+                .and_then(|header| header.meta);
+            Some(SaveSlot { name, meta })
+        })
+        .collect();
+    slots.sort_by_key(|slot| std::cmp::Reverse(slot.meta.as_ref().map_or(0, |m| m.saved_at)));
+    slots
+}
+
+// moves the save from before there were slots into a slot of its own, the
+// name of that slot if there was one. It is migrated once it is loaded
+pub fn import_legacy_save() -> Result<Option<String>, SaveError> {
+    import_save_from(Path::new(LEGACY_SAVE))
+}
+
+fn import_save_from(legacy: &Path) -> Result<Option<String>, SaveError> {
+    if !legacy.is_file() {
+        return Ok(None);
+    }
+    // a slot that was already called "savegame" is not overwritten
+    let slot = (1..)
+        .map(|n| match n {
+            1 => LEGACY_SLOT.to_string(),
+            n => format!("{} {}", LEGACY_SLOT, n),
+        })
+        .find(|slot| !slot_exists(slot))
+        .unwrap_or_else(|| LEGACY_SLOT.to_string());
+    let path = save_path(&slot, SaveFormat::Json);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // renaming fails across file systems, copying does not
+    if fs::rename(legacy, &path).is_err() {
+        fs::copy(legacy, &path)?;
+        fs::remove_file(legacy)?;
+    }
+    Ok(Some(slot))
+}
+
+// removes the save and the recording that goes with it
+pub fn delete_save(slot: &str) -> Result<(), SaveError> {
+    fs::remove_file(find_save(slot).ok_or(SaveError::Missing)?)?;
+    remove_if_exists(&replay_path(slot))
+}
+
+// removes only the save, the recording stays to be watched again
+pub fn delete_save_file(slot: &str) -> Result<(), SaveError> {
+    for format in SaveFormat::ALL {
+        remove_if_exists(&save_path(slot, format))?;
+    }
+    Ok(())
+}
+
+pub fn load_game(slot: &str) -> Result<(Game, Vec<Entity>), SaveError> {
+    read_save(&find_save(slot).ok_or(SaveError::Missing)?)
+}
+
+// loads a save from any path, the format comes from its extension
+pub fn read_save(path: &Path) -> Result<(Game, Vec<Entity>), SaveError> {
+    let save = migrate(read_value(path)?)?;
+    let SaveData { mut game, entities } = serde_json::from_value(save)?;
+    generate_fov_map(&mut game.fov, &game.map);
+    game.compute_fov(&entities);
+    Ok((game, entities))
+}
+
+// brings a save of any older version up to `SAVE_VERSION`
+pub fn migrate(mut save: Value) -> Result<Value, SaveError> {
+    let version = match &save {
+        // saves from before the header were just `(game, entities)`
+        Value::Array(_) => 0,
+        Value::Object(header) => header
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| SaveError::Corrupt("the save has no version".to_string()))?
+            as u32,
+        _ => return Err(SaveError::Corrupt("unknown save layout".to_string())),
+    };
+    if version > SAVE_VERSION {
+        return Err(SaveError::Incompatible { version });
+    }
+
+    for (from_version, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        save = migration(save)?;
+        save["version"] = json!(from_version + 1);
+    }
+    Ok(save)
+}
+
+// adds the header, the very first saves also had tiles made of flags
+// and no seed, random generator or stored levels
+fn migrate_v0(save: Value) -> Result<Value, SaveError> {
+    let Value::Array(mut parts) = save else {
+        return Err(SaveError::Corrupt("expected (game, entities)".to_string()));
+    };
+    let (Some(mut entities), Some(mut game), true) = (parts.pop(), parts.pop(), parts.is_empty())
+    else {
+        return Err(SaveError::Corrupt("expected (game, entities)".to_string()));
+    };
+    let Some(fields) = game.as_object_mut() else {
+        return Err(SaveError::Corrupt("the game is not an object".to_string()));
+    };
+
+    let seed = fields
+        .entry("seed")
+        .or_insert(json!(0))
+        .as_u64()
+        .unwrap_or(0);
+    if !fields.contains_key("rng") {
+        // through bytes, `to_value` can not hold the u128 in the rng
+        let rng = serde_json::to_vec(&rng::new_rng(seed))?;
+        fields.insert("rng".to_string(), serde_json::from_slice(&rng)?);
+    }
+    // the levels above were never kept, only the one the player is on exists
+    let dungeon_level = fields
+        .get("dungeon_level")
+        .and_then(Value::as_u64)
+        .unwrap_or(1);
+    fields
+        .entry("levels")
+        .or_insert_with(|| Value::Array(vec![Value::Null; dungeon_level as usize]));
+
+    if let Some(map) = fields.get_mut("map") {
+        migrate_v0_tiles(map);
+    }
+    if let Some(inventory) = fields.get_mut("inventory") {
+        migrate_v0_entities(inventory);
+    }
+    migrate_v0_entities(&mut entities);
+
+    Ok(json!({ "game": game, "entities": entities }))
+}
+
+fn migrate_v0_tiles(map: &mut Value) {
+    let tiles = map
+        .as_array_mut()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_array_mut)
+        .flatten()
+        .filter_map(Value::as_object_mut);
+    for tile in tiles {
+        if tile.contains_key("kind") {
+            continue;
+        }
+        let passable = tile.remove("passable").and_then(|v| v.as_bool());
+        tile.remove("block_sight");
+        let kind = if passable == Some(true) {
+            "Floor"
+        } else {
+            "Wall"
+        };
+        tile.insert("kind".to_string(), json!(kind));
+    }
+}
+
+fn migrate_v0_entities(entities: &mut Value) {
+    let entities = entities
+        .as_array_mut()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut);
+    for entity in entities {
+        if entity.contains_key("stairs") {
+            continue;
+        }
+        // the only stairs back then were named "stairs" and led down
+        let is_stairs = entity.get("name") == Some(&json!("stairs"));
+        if is_stairs {
+            entity.insert("name".to_string(), json!("stairs down"));
+        }
+        let stairs = if is_stairs {
+            json!("Down")
+        } else {
+            Value::Null
+        };
+        entity.insert("stairs".to_string(), stairs);
+    }
+}
+
+// messages were `(text, color)` pairs, now they also have a category, a turn and a count.
+// nothing tells what the old ones were about so they all become system messages
+fn migrate_v1(mut save: Value) -> Result<Value, SaveError> {
+    let Some(messages) = save
+        .pointer_mut("/game/messages/messages")
+        .and_then(Value::as_array_mut)
+    else {
+        return Err(SaveError::Corrupt("the game has no messages".to_string()));
+    };
+    for message in messages.iter_mut() {
+        if let Value::Array(pair) = message {
+            let text = pair.first().cloned().unwrap_or(Value::Null);
+            let color = pair.get(1).cloned().unwrap_or(Value::Null);
+            *message = json!({
+                "text": text,
+                "color": color,
+                "category": "System",
+                "turn": 0,
+                "count": 1,
+            });
+        }
+    }
+    Ok(save)
+}
+
+// "2024-03-01 17:05" in UTC, good enough to tell saves apart
+pub fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let (hour, minute) = (seconds % 86400 / 3600, seconds % 3600 / 60);
+    // days since 1970-01-01 to a calendar date, from Howard Hinnant's `civil_from_days`
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year, month, day, hour, minute
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action::{apply_action, Action},
+        entity::Stairs,
+        game,
+        message::Category,
+    };
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+    }
+
+    // everything that goes into the save, the message log included
+    fn saved_form(game: &Game, entities: &[Entity]) -> Value {
+        let state = serde_json::to_vec(&(game, entities)).unwrap();
+        serde_json::from_slice(&state).unwrap()
+    }
+
+    #[test]
+    fn every_format_restores_the_same_game() {
+        use_test_data_dir();
+        let (mut game, mut entities) = game::new_game(16);
+        for action in [Action::Move(1, 0), Action::Wait, Action::Move(0, 1)] {
+            apply_action(&mut game, &mut entities, action);
+        }
+        game.messages.set_turn(game.turn);
+        for _ in 0..3 {
+            game.messages
+                .add("Round trip", crate::color::GOLD, Category::Item);
+        }
+        let state = saved_form(&game, &entities);
+        for format in SaveFormat::ALL {
+            let path = save_path("round trip", format);
+            write_save(&path, &game, &entities).unwrap();
+            assert_eq!(SaveFormat::from_path(&path), Some(format));
+            let (loaded_game, loaded_entities) = read_save(&path).unwrap();
+            assert_eq!(
+                saved_form(&loaded_game, &loaded_entities),
+                state,
+                "{:?} changed the game",
+                format
+            );
+            let message = loaded_game.messages.iter().last().unwrap();
+            assert_eq!(message.get_text(), "Round trip (x3)");
+            assert_eq!(message.get_category(), Category::Item);
+            assert_eq!(message.get_turn(), game.turn);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn messages_keep_their_turn_and_category() {
+        use_test_data_dir();
+        let (mut game, entities) = game::new_game(21);
+        let logged = [
+            (3, "You hit the ork.", Category::Combat),
+            (3, "The ork dies!", Category::Combat),
+            (4, "You pick up a scroll.", Category::Item),
+            (9, "You descend.", Category::Level),
+        ];
+        for (turn, text, category) in logged {
+            game.messages.set_turn(turn);
+            game.messages.add(text, crate::color::WHITE, category);
+        }
+        for format in [SaveFormat::Json, SaveFormat::CborGz] {
+            let path = save_path("messages", format);
+            write_save(&path, &game, &entities).unwrap();
+            let (loaded_game, _) = read_save(&path).unwrap();
+            let loaded: Vec<_> = loaded_game
+                .messages
+                .iter()
+                .skip(1)
+                .map(|m| (m.get_turn(), m.get_text(), m.get_category()))
+                .collect();
+            let expected: Vec<_> = logged
+                .iter()
+                .map(|&(turn, text, category)| (turn, text.to_string(), category))
+                .collect();
+            assert_eq!(loaded, expected, "{:?}", format);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    // version 1 messages were only a text and a color
+    #[test]
+    fn migrates_version_1_messages() {
+        let (game, entities) = game::new_game(22);
+        let save = SaveRef {
+            version: 1,
+            meta: SaveMeta::new(&game, &entities),
+            game: &game,
+            entities: &entities,
+        };
+        let mut save: Value = serde_json::from_slice(&serde_json::to_vec(&save).unwrap()).unwrap();
+        save["game"]["messages"]["messages"] = json!([
+            ["Welcome stranger!", { "r": 255, "g": 0, "b": 0 }],
+            ["You hit the ork.", { "r": 255, "g": 255, "b": 255 }],
+        ]);
+
+        let save = migrate(save).unwrap();
+        assert_eq!(save["version"], json!(SAVE_VERSION));
+        let SaveData { game, .. } = serde_json::from_value(save).unwrap();
+        let messages: Vec<_> = game.messages.iter().collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].get_text(), "You hit the ork.");
+        assert_eq!(messages[1].get_color(), crate::color::WHITE);
+        for message in messages {
+            assert_eq!(message.get_category(), Category::System);
+            assert_eq!(message.get_turn(), 0);
+            assert_eq!(message.get_count(), 1);
+        }
+    }
+
+    // every test that saves shares this directory, so each one uses its own slots
+    fn use_test_data_dir() {
+        let dir = env::temp_dir().join("bug-free-fiesta-tests");
+        env::set_var("XDG_DATA_HOME", &dir);
+        env::set_var("APPDATA", &dir);
+        env::set_var("HOME", &dir);
+    }
+
+    #[test]
+    fn imports_the_save_from_before_slots() {
+        use_test_data_dir();
+        let legacy = env::temp_dir().join("bug-free-fiesta-legacy-savegame.json");
+        let data = fs::read(fixture("savegame_v0.json.gz")).unwrap();
+        let mut json = vec![];
+        GzDecoder::new(&data[..]).read_to_end(&mut json).unwrap();
+        fs::write(&legacy, json).unwrap();
+        delete_save_file(LEGACY_SLOT).unwrap();
+
+        let slot = import_save_from(&legacy).unwrap();
+        assert_eq!(slot.as_deref(), Some(LEGACY_SLOT));
+        assert!(!legacy.exists());
+        assert!(list_saves().iter().any(|save| save.name == LEGACY_SLOT));
+        load_game(LEGACY_SLOT).expect("the imported save loads");
+        assert_eq!(import_save_from(&legacy).unwrap(), None);
+    }
+
+    // the savegame.json written by the first version of the game, gzipped to keep it small
+    #[test]
+    fn migrates_a_version_0_save() {
+        let (mut game, mut entities) =
+            read_save(&fixture("savegame_v0.json.gz")).expect("a version 0 save loads");
+        assert_eq!(game.dungeon_level, 1);
+        assert_eq!(game.levels.len(), 1);
+        assert_eq!(entities[PLAYER].get_name(), "Player");
+        assert_eq!(game.inventory.len(), 1);
+        assert!(entities
+            .iter()
+            .any(|e| e.get_stairs() == Some(Stairs::Down) && e.get_name() == "stairs down"));
+        let message = game
+            .messages
+            .iter()
+            .next()
+            .expect("the welcome message is kept");
+        assert_eq!(message.get_category(), Category::System);
+
+        // and the game goes on from there
+        for _ in 0..10 {
+            apply_action(&mut game, &mut entities, Action::Wait);
+        }
+        assert_eq!(game.turn, 10);
+    }
+}
@@ -0,0 +1,83 @@
+use std::{fmt, fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::MapGenerator,
+    message::DEFAULT_MESSAGE_CAPACITY,
+    save::{self, SaveFormat, DEFAULT_SAVE_FORMAT},
+};
+
+const SETTINGS_FILE: &str = "settings.json";
+const DEFAULT_AUTOSAVE_INTERVAL: u32 = 100;
+
+// options read from settings.json in the data directory, fields left out keep their default
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // turns between two autosaves, 0 only autosaves on level changes and when the window closes
+    pub autosave_interval: u32,
+    // the save is deleted as soon as the player dies, so death can not be undone by loading
+    pub permadeath: bool,
+    pub save_format: SaveFormat,
+    // messages kept in the log, 0 keeps all of them
+    pub message_capacity: usize,
+    // older messages go to a log file next to the saves instead of being dropped
+    pub log_old_messages: bool,
+    // generator of each dungeon level from the first one down, the last one is used for
+    // every deeper level. Empty alternates between rooms, BSP and caves
+    pub generators: Vec<MapGenerator>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            autosave_interval: DEFAULT_AUTOSAVE_INTERVAL,
+            permadeath: false,
+            save_format: DEFAULT_SAVE_FORMAT,
+            message_capacity: DEFAULT_MESSAGE_CAPACITY,
+            log_old_messages: false,
+            generators: vec![],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Read(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Read(e) => write!(f, "can not read {}: {}", SETTINGS_FILE, e),
+            SettingsError::Parse(e) => write!(f, "{} is not valid: {}", SETTINGS_FILE, e),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    pub fn path() -> PathBuf {
+        save::data_dir().join(SETTINGS_FILE)
+    }
+
+    // a missing file gives the defaults, a broken one too but with the reason
+    pub fn load() -> (Settings, Option<SettingsError>) {
+        match fs::read_to_string(Settings::path()) {
+            Ok(text) => match serde_json::from_str(&text) {
+                Ok(settings) => (settings, None),
+                Err(e) => (Settings::default(), Some(SettingsError::Parse(e))),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (Settings::default(), None),
+            Err(e) => (Settings::default(), Some(SettingsError::Read(e))),
+        }
+    }
+
+    pub fn is_autosave_due(&self, last_autosave_turn: u32, turn: u32) -> bool {
+        self.autosave_interval > 0
+            && turn.saturating_sub(last_autosave_turn) >= self.autosave_interval
+    }
+}
@@ -0,0 +1,291 @@
+use serde::{Deserialize, Serialize};
+
+use crate::color::{
+    Color, GREEN, LIGHT_BLUE, LIGHT_GREY, LIGHT_RED, LIGHT_VIOLET, ORANGE, SKY, YELLOW,
+};
+
+// something that lasts a few turns on a fighter, see `Entity::tick_effects`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Effect {
+    // loses `strength` hit points every turn
+    Poisoned,
+    // stumbles in a random direction instead of where it wanted to go
+    Confused,
+    // loses its turns
+    Stunned,
+    // acts twice as often
+    Hasted,
+    // acts half as often
+    Slowed,
+    // gets `strength` hit points back every turn
+    Regenerating,
+    // loses `strength` hit points every turn
+    Burning,
+    // only sees what is right next to it
+    Blind,
+}
+
+// what happens when a fighter gets an effect it already has
+enum Stacking {
+    // lasts as long as the longer of the two
+    Refresh,
+    // the turns of both add up
+    Extend,
+    // the strength of both adds up, lasts as long as the longer of the two
+    Intensify,
+}
+
+impl Effect {
+    pub const ALL: [Effect; 8] = [
+        Effect::Poisoned,
+        Effect::Confused,
+        Effect::Stunned,
+        Effect::Hasted,
+        Effect::Slowed,
+        Effect::Regenerating,
+        Effect::Burning,
+        Effect::Blind,
+    ];
+
+    // finishes "You are ..."
+    pub fn name(self) -> &'static str {
+        match self {
+            Effect::Poisoned => "poisoned",
+            Effect::Confused => "confused",
+            Effect::Stunned => "stunned",
+            Effect::Hasted => "hasted",
+            Effect::Slowed => "slowed",
+            Effect::Regenerating => "regenerating",
+            Effect::Burning => "burning",
+            Effect::Blind => "blind",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            Effect::Poisoned => GREEN,
+            Effect::Confused => LIGHT_VIOLET,
+            Effect::Stunned => YELLOW,
+            Effect::Hasted => SKY,
+            Effect::Slowed => LIGHT_BLUE,
+            Effect::Regenerating => LIGHT_RED,
+            Effect::Burning => ORANGE,
+            Effect::Blind => LIGHT_GREY,
+        }
+    }
+
+    fn stacking(self) -> Stacking {
+        match self {
+            Effect::Poisoned => Stacking::Intensify,
+            Effect::Regenerating => Stacking::Extend,
+            _ => Stacking::Refresh,
+        }
+    }
+
+    // getting one of these ends the other instead of adding to it
+    fn opposite(self) -> Option<Effect> {
+        match self {
+            Effect::Hasted => Some(Effect::Slowed),
+            Effect::Slowed => Some(Effect::Hasted),
+            _ => None,
+        }
+    }
+
+    // finishes "You were ..." when it kills the player, only for effects that do damage
+    pub fn death_cause(self) -> Option<&'static str> {
+        match self {
+            Effect::Poisoned => Some("poisoned to death"),
+            Effect::Burning => Some("burned to death"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    effect: Effect,
+    // turns left, it is gone once this reaches 0
+    turns: u32,
+    // hit points per turn for the effects that hurt or heal, unused by the others
+    strength: i32,
+}
+
+impl StatusEffect {
+    pub const fn new(effect: Effect, turns: u32, strength: i32) -> Self {
+        StatusEffect {
+            effect,
+            turns,
+            strength,
+        }
+    }
+    pub fn get_effect(&self) -> Effect {
+        self.effect
+    }
+    pub fn get_turns(&self) -> u32 {
+        self.turns
+    }
+    pub fn get_strength(&self) -> i32 {
+        self.strength
+    }
+}
+
+// at most one of each effect, in the order they were added
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StatusEffects {
+    effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    // the opposite effect if the new one cancelled it out instead of being added
+    pub fn add(&mut self, status: StatusEffect) -> Option<Effect> {
+        if let Some(opposite) = status.effect.opposite() {
+            if self.has(opposite) {
+                self.remove(opposite);
+                return Some(opposite);
+            }
+        }
+        match self.effects.iter_mut().find(|s| s.effect == status.effect) {
+            Some(current) => match status.effect.stacking() {
+                Stacking::Refresh => {
+                    current.turns = current.turns.max(status.turns);
+                    current.strength = current.strength.max(status.strength);
+                }
+                Stacking::Extend => {
+                    current.turns += status.turns;
+                    current.strength = current.strength.max(status.strength);
+                }
+                Stacking::Intensify => {
+                    current.turns = current.turns.max(status.turns);
+                    current.strength += status.strength;
+                }
+            },
+            None => self.effects.push(status),
+        }
+        None
+    }
+
+    pub fn remove(&mut self, effect: Effect) {
+        self.effects.retain(|status| status.effect != effect);
+    }
+
+    pub fn has(&self, effect: Effect) -> bool {
+        self.get(effect).is_some()
+    }
+
+    pub fn get(&self, effect: Effect) -> Option<StatusEffect> {
+        self.effects.iter().find(|s| s.effect == effect).copied()
+    }
+
+    // one turn less for every effect, the ones that ran out are removed and returned
+    pub fn tick(&mut self) -> Vec<Effect> {
+        for status in self.effects.iter_mut() {
+            status.turns = status.turns.saturating_sub(1);
+        }
+        let expired = self
+            .effects
+            .iter()
+            .filter(|status| status.turns == 0)
+            .map(|status| status.effect)
+            .collect();
+        self.effects.retain(|status| status.turns > 0);
+        expired
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StatusEffect> {
+        self.effects.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(statuses: &[StatusEffect]) -> StatusEffects {
+        let mut effects = StatusEffects::default();
+        for &status in statuses {
+            effects.add(status);
+        }
+        effects
+    }
+
+    #[test]
+    fn refreshed_effects_last_as_long_as_the_longer_one() {
+        let effects = with(&[
+            StatusEffect::new(Effect::Confused, 5, 0),
+            StatusEffect::new(Effect::Confused, 3, 0),
+        ]);
+        assert_eq!(effects.get(Effect::Confused).unwrap().get_turns(), 5);
+        let effects = with(&[
+            StatusEffect::new(Effect::Burning, 2, 1),
+            StatusEffect::new(Effect::Burning, 4, 3),
+        ]);
+        assert_eq!(
+            effects.get(Effect::Burning),
+            Some(StatusEffect::new(Effect::Burning, 4, 3))
+        );
+    }
+
+    #[test]
+    fn extended_effects_add_up_their_turns() {
+        let effects = with(&[
+            StatusEffect::new(Effect::Regenerating, 5, 1),
+            StatusEffect::new(Effect::Regenerating, 3, 2),
+        ]);
+        assert_eq!(
+            effects.get(Effect::Regenerating),
+            Some(StatusEffect::new(Effect::Regenerating, 8, 2))
+        );
+    }
+
+    #[test]
+    fn intensified_effects_add_up_their_strength() {
+        let effects = with(&[
+            StatusEffect::new(Effect::Poisoned, 4, 1),
+            StatusEffect::new(Effect::Poisoned, 2, 2),
+        ]);
+        assert_eq!(
+            effects.get(Effect::Poisoned),
+            Some(StatusEffect::new(Effect::Poisoned, 4, 3))
+        );
+        assert_eq!(effects.iter().count(), 1);
+    }
+
+    #[test]
+    fn opposite_effects_cancel_out() {
+        let mut effects = with(&[StatusEffect::new(Effect::Hasted, 10, 0)]);
+        let cancelled = effects.add(StatusEffect::new(Effect::Slowed, 3, 0));
+        assert_eq!(cancelled, Some(Effect::Hasted));
+        assert!(effects.is_empty());
+
+        // with nothing to cancel it is simply added
+        assert_eq!(effects.add(StatusEffect::new(Effect::Slowed, 3, 0)), None);
+        assert!(effects.has(Effect::Slowed));
+        assert_eq!(
+            effects.add(StatusEffect::new(Effect::Hasted, 1, 0)),
+            Some(Effect::Slowed)
+        );
+        assert!(!effects.has(Effect::Hasted));
+    }
+
+    #[test]
+    fn effects_run_out() {
+        let mut effects = with(&[
+            StatusEffect::new(Effect::Stunned, 1, 0),
+            StatusEffect::new(Effect::Blind, 2, 0),
+        ]);
+        assert_eq!(effects.tick(), [Effect::Stunned]);
+        assert_eq!(effects.get(Effect::Blind).unwrap().get_turns(), 1);
+        assert_eq!(effects.tick(), [Effect::Blind]);
+        assert!(effects.is_empty());
+        assert!(effects.tick().is_empty());
+    }
+}
@@ -1,39 +1,166 @@
-use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
 
-use crate::entity::Entity;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    color::{Color, GOLD, WHITE},
+    entity::Entity,
+};
 
 pub const MAP_WIDTH: i32 = 80;
 pub const MAP_HEIGHT: i32 = 43;
 
 pub type Map = Vec<Vec<Tile>>;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TileKind {
+    Floor,
+    Wall,
+    DoorOpen,
+    DoorClosed,
+    // a closed door that needs a key to open
+    DoorLocked,
+    Water,
+    Lava,
+    Rubble,
+    Grass,
+    Chasm,
+}
+
+// how a tile kind looks and behaves, `light` and `dark` are the background
+// colors of the tile while it is in view and once it is only remembered
+#[derive(Clone, Copy, Debug)]
+pub struct TileInfo {
+    pub glyph: char,
+    pub glyph_color: Color,
+    pub light: Color,
+    pub dark: Color,
+    pub passable: bool,
+    pub block_sight: bool,
+    pub movement_cost: i32,
+}
+
+const COLOR_DARK_WALL: Color = Color::new(0, 0, 100);
+const COLOR_DARK_GROUND: Color = Color::new(50, 50, 150);
+const COLOR_LIGHT_WALL: Color = Color::new(130, 110, 50);
+const COLOR_LIGHT_GROUND: Color = Color::new(200, 180, 50);
+const COLOR_WOOD: Color = Color::new(110, 60, 20);
+
+impl TileKind {
+    pub fn info(self) -> TileInfo {
+        let floor = TileInfo {
+            glyph: ' ',
+            glyph_color: WHITE,
+            light: COLOR_LIGHT_GROUND,
+            dark: COLOR_DARK_GROUND,
+            passable: true,
+            block_sight: false,
+            movement_cost: 1,
+        };
+        let wall = TileInfo {
+            light: COLOR_LIGHT_WALL,
+            dark: COLOR_DARK_WALL,
+            passable: false,
+            block_sight: true,
+            ..floor
+        };
+        match self {
+            TileKind::Floor => floor,
+            TileKind::Wall => wall,
+            TileKind::DoorOpen => TileInfo {
+                glyph: '\'',
+                glyph_color: COLOR_WOOD,
+                ..floor
+            },
+            TileKind::DoorClosed => TileInfo {
+                glyph: '+',
+                glyph_color: COLOR_WOOD,
+                ..wall
+            },
+            TileKind::DoorLocked => TileInfo {
+                glyph: '+',
+                glyph_color: GOLD,
+                ..wall
+            },
+            TileKind::Water => TileInfo {
+                glyph: '~',
+                glyph_color: Color::new(150, 200, 255),
+                light: Color::new(40, 80, 200),
+                dark: Color::new(20, 30, 110),
+                movement_cost: 3,
+                ..floor
+            },
+            TileKind::Lava => TileInfo {
+                glyph: '~',
+                glyph_color: Color::new(255, 200, 0),
+                light: Color::new(200, 50, 0),
+                dark: Color::new(90, 20, 0),
+                passable: false,
+                ..floor
+            },
+            TileKind::Rubble => TileInfo {
+                glyph: ':',
+                glyph_color: Color::new(90, 80, 60),
+                movement_cost: 2,
+                ..floor
+            },
+            TileKind::Grass => TileInfo {
+                glyph: '"',
+                glyph_color: Color::new(30, 120, 20),
+                light: Color::new(110, 170, 50),
+                dark: Color::new(30, 70, 60),
+                ..floor
+            },
+            TileKind::Chasm => TileInfo {
+                light: Color::new(20, 20, 20),
+                dark: Color::new(5, 5, 15),
+                passable: false,
+                ..floor
+            },
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Tile {
-    passable: bool,
-    block_sight: bool,
+    kind: TileKind,
     explored: bool,
 }
 
 impl Tile {
-    pub fn empty() -> Self {
+    pub fn new(kind: TileKind) -> Self {
         Tile {
-            passable: true,
-            block_sight: false,
+            kind,
             explored: false,
         }
     }
+    pub fn empty() -> Self {
+        Tile::new(TileKind::Floor)
+    }
     pub fn wall() -> Self {
-        Tile {
-            passable: false,
-            block_sight: true,
-            explored: false,
-        }
+        Tile::new(TileKind::Wall)
+    }
+    pub fn get_kind(&self) -> TileKind {
+        self.kind
+    }
+    // keeps the explored flag, unlike replacing the whole tile
+    pub fn set_kind(&mut self, kind: TileKind) {
+        self.kind = kind;
     }
     pub fn get_is_block_sight(&self) -> bool {
-        self.block_sight
+        self.kind.info().block_sight
     }
     pub fn get_is_passable(&self) -> bool {
-        self.passable
+        self.kind.info().passable
+    }
+    pub fn get_movement_cost(&self) -> i32 {
+        self.kind.info().movement_cost
+    }
+    pub fn is_door(&self) -> bool {
+        matches!(
+            self.kind,
+            TileKind::DoorOpen | TileKind::DoorClosed | TileKind::DoorLocked
+        )
     }
     pub fn get_is_explored(&self) -> bool {
         self.explored
@@ -51,4 +178,38 @@ impl Tile {
             .iter()
             .any(|entity| entity.get_is_blocks() && entity.get_coordinates() == (x, y))
     }
+
+    // every passable tile reachable from (x, y) moving up, down, left or right,
+    // in the order they are reached, so the last one is the farthest away.
+    // doors count as passable since they can be opened
+    pub fn flood_fill(x: i32, y: i32, map: &Map) -> Vec<(i32, i32)> {
+        Tile::flood_fill_with(x, y, map, |tile| tile.get_is_passable() || tile.is_door())
+    }
+
+    pub fn flood_fill_with<F>(x: i32, y: i32, map: &Map, can_pass: F) -> Vec<(i32, i32)>
+    where
+        F: Fn(&Tile) -> bool,
+    {
+        let mut visited = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        let mut reached = vec![];
+        if !can_pass(&map[x as usize][y as usize]) {
+            return reached;
+        }
+        let mut queue = VecDeque::from([(x, y)]);
+        visited[x as usize][y as usize] = true;
+        while let Some((x, y)) = queue.pop_front() {
+            reached.push((x, y));
+            for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                if nx < 0 || ny < 0 || nx >= MAP_WIDTH || ny >= MAP_HEIGHT {
+                    continue;
+                }
+                let (ux, uy) = (nx as usize, ny as usize);
+                if !visited[ux][uy] && can_pass(&map[ux][uy]) {
+                    visited[ux][uy] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        reached
+    }
 }
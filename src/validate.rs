@@ -0,0 +1,163 @@
+use crate::{
+    entity::{Entity, FREDERIC, PLAYER},
+    rng::GameRng,
+    room::Room,
+    tile::{Map, Tile, MAP_HEIGHT, MAP_WIDTH},
+};
+
+// result of checking a freshly generated level, everything is measured from
+// the player position
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MapReport {
+    // passable tiles the player can not walk to
+    pub unreachable_floor: Vec<(i32, i32)>,
+    // indices into `entities` standing on floor the player can not walk to
+    pub unreachable_entities: Vec<usize>,
+    // indices into `entities` placed on a tile that is not passable
+    pub entities_in_walls: Vec<usize>,
+}
+
+impl MapReport {
+    pub fn is_valid(&self) -> bool {
+        self.unreachable_floor.is_empty()
+            && self.unreachable_entities.is_empty()
+            && self.entities_in_walls.is_empty()
+    }
+}
+
+fn reachable_tiles(map: &Map, entities: &[Entity]) -> Vec<Vec<bool>> {
+    let mut reachable = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let (x, y) = entities[PLAYER].get_coordinates();
+    for (x, y) in Tile::flood_fill(x, y, map) {
+        reachable[x as usize][y as usize] = true;
+    }
+    reachable
+}
+
+pub fn validate_map(map: &Map, entities: &[Entity]) -> MapReport {
+    let reachable = reachable_tiles(map, entities);
+    let mut report = MapReport::default();
+
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            let (ux, uy) = (x as usize, y as usize);
+            if map[ux][uy].get_is_passable() && !reachable[ux][uy] {
+                report.unreachable_floor.push((x, y));
+            }
+        }
+    }
+
+    for (id, entity) in entities.iter().enumerate() {
+        let (x, y) = entity.get_coordinates();
+        let is_on_map = x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT;
+        if !is_on_map || !map[x as usize][y as usize].get_is_passable() {
+            report.entities_in_walls.push(id);
+        } else if !reachable[x as usize][y as usize] {
+            report.unreachable_entities.push(id);
+        }
+    }
+
+    report
+}
+
+// tries to fix what `validate_map` found and returns the report of the repaired map,
+// if it is still not valid the level should be generated again
+pub fn repair_map(map: &mut Map, entities: &mut Vec<Entity>, rng: &mut GameRng) -> MapReport {
+    // the player, Frederic and the stairs get their tile carved out,
+    // monsters and items stuck in a wall are simply dropped
+    let report = validate_map(map, entities);
+    for &id in report.entities_in_walls.iter().rev() {
+        let (x, y) = entities[id].get_coordinates();
+        let is_on_map = x > 0 && y > 0 && x < MAP_WIDTH - 1 && y < MAP_HEIGHT - 1;
+        let must_keep = id == PLAYER || id == FREDERIC || entities[id].get_stairs().is_some();
+        if must_keep && is_on_map {
+            map[x as usize][y as usize] = Tile::empty();
+        } else if id != PLAYER && id != FREDERIC {
+            entities.remove(id);
+        }
+    }
+
+    // join every pocket of floor the player can not reach with the closest reachable tile,
+    // each tunnel merges at least one pocket so this always ends
+    loop {
+        let reachable = reachable_tiles(map, entities);
+        let mut reachable_list = vec![];
+        let mut unreachable = None;
+        for x in 0..MAP_WIDTH {
+            for y in 0..MAP_HEIGHT {
+                let (ux, uy) = (x as usize, y as usize);
+                if reachable[ux][uy] {
+                    reachable_list.push((x, y));
+                } else if unreachable.is_none() && map[ux][uy].get_is_passable() {
+                    unreachable = Some((x, y));
+                }
+            }
+        }
+
+        let Some((x, y)) = unreachable else {
+            break;
+        };
+        let closest = reachable_list
+            .into_iter()
+            .min_by_key(|&(rx, ry)| (rx - x).pow(2) + (ry - y).pow(2));
+        match closest {
+            Some(closest) => Room::connect((x, y), closest, map, rng),
+            None => break,
+        }
+    }
+
+    validate_map(map, entities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entity::Stairs,
+        game::{make_map, starting_entities, MapGenerator},
+        rng,
+    };
+
+    // a few hundred seeds keep `cargo test` quick, the ignored test goes through thousands:
+    // cargo test --release -- --ignored
+    const SEEDS: u64 = 300;
+    const MANY_SEEDS: u64 = 5000;
+
+    fn check_generated_levels(seeds: std::ops::Range<u64>) {
+        let generators = [MapGenerator::Rooms, MapGenerator::Bsp, MapGenerator::Caves];
+        for seed in seeds {
+            for generator in generators {
+                let mut rng = rng::new_rng(seed);
+                let mut entities = starting_entities();
+                let map = make_map(&mut entities, generator, &mut rng);
+                let report = validate_map(&map, &entities);
+                assert!(
+                    report.is_valid(),
+                    "seed {} {:?}: {:?}",
+                    seed,
+                    generator,
+                    report
+                );
+                assert!(
+                    entities
+                        .iter()
+                        .any(|e| e.get_stairs() == Some(Stairs::Down)),
+                    "seed {} {:?} has no stairs down",
+                    seed,
+                    generator
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_generated_level_is_valid() {
+        check_generated_levels(0..SEEDS);
+    }
+
+    #[test]
+    #[ignore]
+    fn thousands_of_generated_levels_are_valid() {
+        check_generated_levels(SEEDS..MANY_SEEDS);
+    }
+}